@@ -0,0 +1,6 @@
+-- Add down migration script here
+alter table file drop column inode;
+alter table file drop column device;
+alter table file drop column created;
+alter table file drop column modified;
+alter table file drop column size;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+alter table file add column size integer not null default 0;
+alter table file add column modified integer;
+alter table file add column created integer;
+alter table file add column device integer not null default 0;
+alter table file add column inode integer not null default 0;
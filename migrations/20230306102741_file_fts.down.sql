@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop trigger if exists file_fts_au;
+drop trigger if exists file_fts_ad;
+drop trigger if exists file_fts_ai;
+drop table if exists file_fts;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+-- full text index over file names and paths, the rows themselves stay in `file`.
+-- `file` has no integer primary key, so after a `vacuum` run
+-- `insert into file_fts(file_fts) values('rebuild');` to re-sync the rowids.
+create virtual table if not exists file_fts using fts5(
+    file_name,
+    full_path,
+    content = 'file',
+    content_rowid = 'rowid',
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+create trigger if not exists file_fts_ai after insert on file begin
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+create trigger if not exists file_fts_ad after delete on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+end;
+
+create trigger if not exists file_fts_au after update of file_name, full_path on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+insert into file_fts(file_fts) values ('rebuild');
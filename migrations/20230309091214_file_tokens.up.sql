@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- lower case words of file_name split on separators, filled by `scan`.
+alter table file add column tokens text not null default '';
+
+-- rough split for rows scanned before, the next scan rewrites them.
+update file set tokens = lower(
+    replace(replace(replace(replace(replace(replace(replace(replace(replace(replace(
+        file_name, '.', ' '), '_', ' '), '-', ' '), '[', ' '), ']', ' '), '(', ' '), ')', ' '), '{', ' '), '}', ' '), '  ', ' ')
+);
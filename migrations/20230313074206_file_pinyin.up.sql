@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- full pinyin and initials of the chinese words in file_name, filled by `scan`.
+alter table file add column pinyin text not null default '';
@@ -0,0 +1,4 @@
+-- Add down migration script here
+drop index if exists idx_file_volume_id;
+alter table file drop column volume_id;
+drop table if exists volumes;
@@ -0,0 +1,15 @@
+-- Add up migration script here
+-- disks and shares files were scanned on, `id` is the filesystem uuid when there is one.
+create table if not exists volumes (
+    id text primary key,
+    uuid text,
+    label text,
+    fs_type text,
+    mount_point text not null,
+    hostname text not null,
+    last_seen integer not null
+);
+
+alter table file add column volume_id text references volumes(id);
+
+create index if not exists idx_file_volume_id on file(volume_id);
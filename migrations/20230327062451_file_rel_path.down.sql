@@ -0,0 +1,47 @@
+-- Add down migration script here
+-- rows of the same path on different volumes collapse into one.
+create table file_old (
+   id text primary key,
+   full_path text not null,
+   file_name text not null,
+   hostname text not null,
+   dir boolean not null default 0,
+   timestamp integer not null,
+   size integer not null default 0,
+   modified integer,
+   created integer,
+   device integer not null default 0,
+   inode integer not null default 0,
+   tokens text not null default '',
+   pinyin text not null default '',
+   volume_id text references volumes(id),
+
+   unique(full_path)
+);
+
+insert or ignore into file_old(id, full_path, file_name, hostname, dir, timestamp, size, modified, created,
+                               device, inode, tokens, pinyin, volume_id)
+select id, full_path, file_name, hostname, dir, timestamp, size, modified, created,
+       device, inode, tokens, pinyin, volume_id
+from file;
+
+drop table file;
+alter table file_old rename to file;
+
+create index if not exists idx_file_file_name on file(file_name);
+create index if not exists idx_file_volume_id on file(volume_id);
+
+create trigger if not exists file_fts_ai after insert on file begin
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+create trigger if not exists file_fts_ad after delete on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+end;
+
+create trigger if not exists file_fts_au after update of file_name, full_path on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+insert into file_fts(file_fts) values ('rebuild');
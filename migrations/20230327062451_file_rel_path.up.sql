@@ -0,0 +1,62 @@
+-- Add up migration script here
+-- files are keyed by their volume and the path inside it, so a disk mounted somewhere else
+-- updates its rows instead of adding a second copy. `full_path` is the mount point of the
+-- volume joined with `rel_path`, kept up to date by `scan`. sqlite can not drop the old
+-- unique(full_path), so the table is rebuilt.
+create table file_new (
+   id text primary key,
+   full_path text not null,
+   file_name text not null,
+   hostname text not null,
+   dir boolean not null default 0,
+   timestamp integer not null,
+   size integer not null default 0,
+   modified integer,
+   created integer,
+   device integer not null default 0,
+   inode integer not null default 0,
+   tokens text not null default '',
+   pinyin text not null default '',
+   volume_id text references volumes(id),
+   rel_path text not null,
+   last_seen integer not null
+);
+
+insert into file_new(id, full_path, file_name, hostname, dir, timestamp, size, modified, created,
+                     device, inode, tokens, pinyin, volume_id, rel_path, last_seen)
+select f.id, f.full_path, f.file_name, f.hostname, f.dir, f.timestamp, f.size, f.modified, f.created,
+       f.device, f.inode, f.tokens, f.pinyin,
+       case when v.id is null then null else f.volume_id end,
+       case
+           when v.id is null then f.full_path
+           when v.mount_point = '/' then substr(f.full_path, 2)
+           else substr(f.full_path, length(v.mount_point) + 2)
+       end,
+       f.timestamp
+from file f
+left join volumes v on v.id = f.volume_id
+    and (v.mount_point = '/' or substr(f.full_path, 1, length(v.mount_point) + 1) = v.mount_point || '/');
+
+drop table file;
+alter table file_new rename to file;
+
+create index if not exists idx_file_file_name on file(file_name);
+create index if not exists idx_file_volume_id on file(volume_id);
+create unique index if not exists idx_file_volume_rel_path on file(volume_id, rel_path) where volume_id is not null;
+create unique index if not exists idx_file_full_path on file(full_path) where volume_id is null;
+
+-- the old triggers went with the old table, and the rowids changed.
+create trigger if not exists file_fts_ai after insert on file begin
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+create trigger if not exists file_fts_ad after delete on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+end;
+
+create trigger if not exists file_fts_au after update of file_name, full_path on file begin
+    insert into file_fts(file_fts, rowid, file_name, full_path) values ('delete', old.rowid, old.file_name, old.full_path);
+    insert into file_fts(rowid, file_name, full_path) values (new.rowid, new.file_name, new.full_path);
+end;
+
+insert into file_fts(file_fts) values ('rebuild');
@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop index if exists idx_file_hash;
+drop index if exists idx_file_size;
+alter table file drop column hash;
+alter table file drop column partial_hash;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- blake3 of the first and last 64 KiB, and of the whole file, filled by `scan --hash` for files
+-- that share their size with another one.
+alter table file add column partial_hash text;
+alter table file add column hash text;
+
+create index if not exists idx_file_size on file(size);
+create index if not exists idx_file_hash on file(hash);
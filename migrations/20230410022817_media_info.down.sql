@@ -0,0 +1,4 @@
+-- Add down migration script here
+drop trigger if exists media_info_file_au;
+drop index if exists idx_media_info_height;
+drop table if exists media_info;
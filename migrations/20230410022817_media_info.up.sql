@@ -0,0 +1,21 @@
+-- Add up migration script here
+-- duration, size and codecs of videos, read by `scan`. a row with nothing but `file_id` marks
+-- a file that was read without anything found.
+create table if not exists media_info (
+    file_id text primary key references file(id) on delete cascade,
+    duration_ms integer,
+    width integer,
+    height integer,
+    video_codec text,
+    audio_codec text,
+    created integer
+);
+
+create index if not exists idx_media_info_height on media_info(height);
+
+-- a changed file is read again.
+create trigger if not exists media_info_file_au after update of size, modified on file
+    when old.size != new.size or old.modified is not new.modified
+begin
+    delete from media_info where file_id = old.id;
+end;
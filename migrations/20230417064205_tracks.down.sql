@@ -0,0 +1,11 @@
+-- Add down migration script here
+drop trigger if exists media_info_file_au;
+create trigger if not exists media_info_file_au after update of size, modified on file
+    when old.size != new.size or old.modified is not new.modified
+begin
+    delete from media_info where file_id = old.id;
+end;
+
+drop index if exists idx_tracks_language;
+drop table if exists tracks;
+alter table media_info drop column title;
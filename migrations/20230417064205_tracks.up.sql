@@ -0,0 +1,27 @@
+-- Add up migration script here
+-- the video, audio and subtitle tracks of a file, with their language.
+alter table media_info add column title text;
+
+create table if not exists tracks (
+    file_id text not null references file(id) on delete cascade,
+    number integer not null,
+    kind text not null,
+    codec text,
+    language text,
+    name text,
+
+    primary key(file_id, number)
+);
+
+create index if not exists idx_tracks_language on tracks(kind, language);
+
+drop trigger if exists media_info_file_au;
+create trigger if not exists media_info_file_au after update of size, modified on file
+    when old.size != new.size or old.modified is not new.modified
+begin
+    delete from media_info where file_id = old.id;
+    delete from tracks where file_id = old.id;
+end;
+
+-- read every file again for its tracks, codecs are named the same across containers now.
+delete from media_info;
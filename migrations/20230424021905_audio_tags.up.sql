@@ -0,0 +1,26 @@
+-- Add up migration script here
+-- artist, album and the like of music files, from id3, vorbis comments and itunes atoms.
+create table if not exists audio_tags (
+    file_id text primary key not null references file(id) on delete cascade,
+    title text,
+    artist text,
+    album text,
+    album_artist text,
+    genre text,
+    track integer,
+    year integer
+);
+
+create index if not exists idx_audio_tags_artist on audio_tags(artist);
+
+drop trigger if exists media_info_file_au;
+create trigger if not exists media_info_file_au after update of size, modified on file
+    when old.size != new.size or old.modified is not new.modified
+begin
+    delete from media_info where file_id = old.id;
+    delete from tracks where file_id = old.id;
+    delete from audio_tags where file_id = old.id;
+end;
+
+-- read m4a files again for their tags, and read mp3 and flac files for the first time.
+delete from media_info;
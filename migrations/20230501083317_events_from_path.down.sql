@@ -0,0 +1,3 @@
+-- Add down migration script here
+delete from events where event_type in ('modify', 'rename');
+alter table events drop column from_path;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- where a renamed file was before, for `rename` events recorded by `watch`.
+alter table events add column from_path text;
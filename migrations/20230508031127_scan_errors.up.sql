@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- paths a scan could not read or save, `started` is when that scan began.
+create table if not exists scan_errors (
+    id integer primary key autoincrement,
+    scan_id text not null,
+    root text not null,
+    hostname text not null,
+    started integer not null,
+    path text not null,
+    kind text not null,
+    message text not null
+);
+
+create index if not exists idx_scan_errors_scan_id on scan_errors(scan_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+alter table file drop column changed;
+drop table if exists scans;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- one row per run of `scan`, its errors are in scan_errors under the same id.
+create table if not exists scans (
+    id text primary key,
+    root text not null,
+    hostname text not null,
+    started integer not null,
+    finished integer not null,
+    entries integer not null,
+    new integer not null,
+    updated integer not null,
+    removed integer not null,
+    errors integer not null
+);
+
+create index if not exists idx_scans_root on scans(root, started);
+
+-- last_seen of a file when its size or modification time last changed, or it first showed up.
+alter table file add column changed integer not null default 0;
+update file set changed = last_seen;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop table if exists scan_checkpoints;
+alter table scans drop column complete;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- a scan is complete once it walked, hashed and read all there was, until then `scan --resume`
+-- goes on with it.
+alter table scans add column complete integer not null default 1;
+
+-- directories an unfinished scan walked and saved all of, they are skipped when it resumes.
+create table if not exists scan_checkpoints (
+    scan_id text not null,
+    path text not null,
+    primary key (scan_id, path)
+);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- `full_path` in nfc, what `find --full-path` and `--path` match against. `full_path` stays as
+-- it is on disk, files are opened by it. filled by `scan`, rows from before by `Sqlite::new`.
+alter table file add column search_path text not null default '';
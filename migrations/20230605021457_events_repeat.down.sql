@@ -0,0 +1,4 @@
+-- Add down migration script here
+drop index if exists idx_events_full_path;
+delete from events where rowid not in (select max(rowid) from events group by event_type, full_path);
+create unique index if not exists file_event_idx ON events(event_type, full_path);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- every change of a file is an event, not only its first one of each type.
+drop index if exists file_event_idx;
+create index if not exists idx_events_full_path on events(full_path, timestamp);
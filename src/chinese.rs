@@ -0,0 +1,49 @@
+use pinyin::ToPinyin;
+
+/// fold traditional chinese into simplified, so a name matches in either form.
+pub fn fold(s: &str) -> String {
+    fast2s::convert(s)
+}
+
+/// full pinyin and pinyin initials of every word that has chinese in it, other characters
+/// are kept as they are: `go语言基础` gives `goyuyanjichu goyyjc`.
+/// polyphonic characters only get their most common reading.
+pub fn pinyin_key(words: &[String]) -> String {
+    let mut key = vec![];
+    for word in words {
+        if !word.chars().any(|c| c.to_pinyin().is_some()) {
+            continue;
+        }
+
+        let mut full = String::new();
+        let mut initials = String::new();
+        for c in word.chars() {
+            match c.to_pinyin() {
+                Some(p) => {
+                    full.push_str(p.plain());
+                    initials.push_str(p.first_letter());
+                }
+                None => {
+                    full.push(c);
+                    initials.push(c);
+                }
+            }
+        }
+        key.push(full);
+        key.push(initials);
+    }
+    key.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pinyin_key() {
+        let words = vec!["go语言基础".to_string(), "mp4".to_string()];
+        assert_eq!(pinyin_key(&words), "goyuyanjichu goyyjc");
+        assert_eq!(pinyin_key(&["show".to_string()]), "");
+        assert_eq!(fold("語言基礎"), "语言基础");
+    }
+}
@@ -1,7 +1,10 @@
 use crate::database::Sqlite;
+use crate::dupes::DupesCommand;
 use crate::find::FindCommand;
 use crate::scan::ScanCommand;
+use crate::scans::ScansCommand;
 use crate::settings::Settings;
+use crate::watch::WatchCommand;
 use clap::{Parser, Subcommand};
 use eyre::{Result, WrapErr};
 
@@ -24,16 +27,34 @@ pub enum Commands {
     /// trim name
     #[command(flatten)]
     Find(FindCommand),
+    #[command(flatten)]
+    Dupes(DupesCommand),
+    #[command(flatten)]
+    Scans(ScansCommand),
+    #[command(flatten)]
+    Watch(WatchCommand),
 }
 
 impl Commands {
+    /// what is logged when `RUST_LOG` is not set: only warnings while a scan draws its
+    /// progress line, or is to be quiet, so nothing breaks into them.
+    pub fn log_filter(&self) -> &'static str {
+        match self {
+            Self::Scan(scan) if scan.quiet_logs() => "find_videos=warn",
+            _ => "find_videos=debug",
+        }
+    }
+
     pub async fn run(self) -> Result<()> {
         let settings = Settings::new().wrap_err("could not load settings.")?;
         let mut db = Sqlite::new(&settings.db_path).await?;
 
         match self {
-            Self::Scan(scan) => scan.run(&mut db).await,
+            Self::Scan(scan) => scan.run(&mut db, &settings).await,
             Self::Find(find) => find.run(&mut db, &settings).await,
+            Self::Dupes(dupes) => dupes.run(&mut db).await,
+            Self::Scans(scans) => scans.run(&mut db).await,
+            Self::Watch(watch) => watch.run(&mut db, &settings).await,
         }
     }
 }
@@ -1,24 +1,103 @@
+use crate::chinese::{fold, pinyin_key};
 use crate::event::{Event, EventType};
 use crate::file::File;
+use crate::media::FileFilter;
+use crate::media_info::{language_codes, readable_extensions, MediaInfo, TrackKind};
+use crate::regexp;
+use crate::report::{ErrorKind, ScanError, ScanReport};
+use crate::search::{escape_like, normalize, tokenize, Kind, SearchMode, SearchQuery, Sort};
+use crate::util::hostname;
+use crate::volume::Volume;
+use async_stream::try_stream;
 use async_trait::async_trait;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow},
-    Result, Row,
+    sqlite::{
+        SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow,
+        SqliteSynchronous,
+    },
+    QueryBuilder, Result, Row,
 };
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
-use tracing::debug;
+use tracing::{debug, trace};
 
 #[async_trait]
 pub trait Database: Send + Sync {
     async fn save(&mut self, f: &File) -> Result<()>;
-    async fn save_bulk(&mut self, f: &[File]) -> Result<()>;
+    /// save all of `f` in one transaction.
+    async fn save_bulk(&mut self, f: &[File]) -> Result<Saved>;
+    #[allow(dead_code)]
     async fn update(&self, h: &File) -> Result<()>;
     async fn file_count(&self) -> Result<i64>;
     async fn event_count(&self) -> Result<i64>;
-    async fn query_file(&self, query: &str) -> Result<Vec<File>>;
+    /// files matching `q`, streamed as sqlite hands them out.
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> BoxStream<'a, Result<File>>;
+    /// delete every file under `root` on one of `volumes`, or on no volume and seen from this
+    /// host, that was not seen since `since`, and record a delete event for it, returning the
+    /// removed paths. files at or under `keep`, paths that could not be walked, are left alone,
+    /// and so are files `filter` does not match, the walk would not have seen them anyway.
+    async fn remove_stale(
+        &mut self,
+        root: &str,
+        volumes: &[Volume],
+        since: DateTime<Utc>,
+        keep: &[String],
+        filter: &FileFilter,
+    ) -> Result<Vec<String>>;
+    /// like `save`, for a file that changed on disk rather than showed up.
+    async fn save_modified(&mut self, f: &File) -> Result<()>;
+    /// move the file at `from` and everything under it to where `to` is, keeping their ids,
+    /// hashes and media info. false when `from` is not known on the volume of `to`.
+    async fn rename(&mut self, from: &str, to: &File) -> Result<bool>;
+    /// delete the file at `path` and everything under it, returning the removed paths.
+    async fn remove(&mut self, path: &str) -> Result<Vec<String>>;
+    /// insert the volume or refresh where and when it was last seen, moving the paths of its
+    /// files along when it is mounted somewhere else.
+    async fn save_volume(&mut self, v: &Volume) -> Result<()>;
+    async fn volumes(&self) -> Result<Vec<Volume>>;
+    /// files without a partial hash that share their size with another file, where one of the
+    /// two is under `root` and seen since `since`. the other may be anywhere.
+    async fn partial_hash_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>>;
+    /// files without a full hash that share their size and partial hash with another file,
+    /// where one of the two is under `root` and seen since `since`. the other may be anywhere.
+    async fn full_hash_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>>;
+    async fn save_hash(&mut self, f: &File) -> Result<()>;
+    /// files of at least `min_size` bytes with the same hash as another, biggest first and
+    /// grouped by hash.
+    async fn dupes(&self, min_size: i64) -> Result<Vec<Vec<File>>>;
+    /// files under `root` seen since `since` that there is a reader for and that were not read
+    /// for media info yet.
+    async fn media_info_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>>;
+    async fn save_media_info(&mut self, m: &MediaInfo) -> Result<()>;
+    /// record a scan and its errors, the ones saved before for it are replaced. the checkpoints
+    /// of a complete scan are dropped.
+    async fn save_report(&mut self, r: &ScanReport) -> Result<()>;
+    /// record how far a scan got and that it walked and saved all of `dirs`.
+    async fn save_checkpoints(&mut self, r: &ScanReport, dirs: &[String]) -> Result<()>;
+    /// the last scan of `root` on `hostname` when it did not complete.
+    async fn unfinished_scan(&self, root: &str, hostname: &str) -> Result<Option<ScanReport>>;
+    async fn checkpoints(&self, scan_id: &str) -> Result<Vec<String>>;
+    /// the last `limit` scans, of `root` only when given, newest first and without errors.
+    async fn scans(&self, root: Option<&str>, limit: i64) -> Result<Vec<ScanReport>>;
+    /// the scan whose id is or starts with `id`, with its errors.
+    async fn scan_report(&self, id: &str) -> Result<Option<ScanReport>>;
+}
+
+/// rows per insert statement, well below the 32766 parameters sqlite takes.
+const BULK_ROWS: usize = 500;
+
+/// what `save_bulk` made of the files it was given.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Saved {
+    /// files not known before.
+    pub new: u64,
+    /// known files whose size or modification time changed.
+    pub updated: u64,
 }
 
 pub struct Sqlite {
@@ -39,9 +118,16 @@ impl Sqlite {
 
         let opts = SqliteConnectOptions::from_str(path.as_os_str().to_str().unwrap())?
             .journal_mode(SqliteJournalMode::Wal)
+            // with wal, a crash may lose the last transactions but never corrupts the file.
+            .synchronous(SqliteSynchronous::Normal)
+            // 64 MiB of pages, the default 2 MiB is outgrown by the indexes of a big catalog.
+            .pragma("cache_size", "-65536")
             .create_if_missing(true);
 
-        let pool = SqlitePoolOptions::new().connect_with(opts).await?;
+        let pool = SqlitePoolOptions::new()
+            .after_connect(|conn, _| Box::pin(regexp::register(conn)))
+            .connect_with(opts)
+            .await?;
 
         Self::setup_db(&pool).await?;
 
@@ -52,45 +138,558 @@ impl Sqlite {
         debug!("running sqlite database setup.");
 
         sqlx::migrate!("./migrations").run(pool).await?;
+        Self::fill_pinyin(pool).await?;
+        Self::fill_search_paths(pool).await?;
+
+        Ok(())
+    }
+
+    /// the search path of files saved before there was a column for it, once like
+    /// `fill_pinyin`.
+    async fn fill_search_paths(pool: &SqlitePool) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("pragma user_version")
+            .fetch_one(pool)
+            .await?;
+        if version >= 2 {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        let files: Vec<(String, String)> =
+            sqlx::query_as("select id, full_path from file where search_path = ''")
+                .fetch_all(&mut tx)
+                .await?;
+        Self::save_search_paths(&mut tx, &files).await?;
+        sqlx::query("pragma user_version = 2")
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        debug!("filled the search path of {} files.", files.len());
+
+        Ok(())
+    }
+
+    /// set the search path of each of `files`, given as id and full path, after their paths
+    /// were changed in sql.
+    async fn save_search_paths(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        files: &[(String, String)],
+    ) -> Result<()> {
+        for (id, path) in files {
+            sqlx::query("update file set search_path = ?2 where id = ?1")
+                .bind(id.as_str())
+                .bind(normalize(path))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// the pinyin of files saved before there was a column for it, it takes rust to work it
+    /// out so a migration cannot. done once, `user_version` records that it was.
+    async fn fill_pinyin(pool: &SqlitePool) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("pragma user_version")
+            .fetch_one(pool)
+            .await?;
+        if version >= 1 {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        let files: Vec<(String, String)> =
+            sqlx::query_as("select id, file_name from file where pinyin = ''")
+                .fetch_all(&mut tx)
+                .await?;
+        let mut filled = 0;
+        for (id, name) in files {
+            let pinyin = pinyin_key(&tokenize(&fold(&name)));
+            if pinyin.is_empty() {
+                continue;
+            }
+            sqlx::query("update file set pinyin = ?2 where id = ?1")
+                .bind(id)
+                .bind(pinyin)
+                .execute(&mut tx)
+                .await?;
+            filled += 1;
+        }
+        sqlx::query("pragma user_version = 1")
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        debug!("filled the pinyin of {filled} files.");
 
         Ok(())
     }
 
     async fn save_event(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, e: &Event) -> Result<()> {
-        let event_type = match e.event_type {
-            EventType::Create => "create",
-            EventType::Delete => "delete",
+        Self::save_events(tx, std::slice::from_ref(e)).await
+    }
+
+    /// insert `events` in as few statements as sqlite allows.
+    async fn save_events(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        events: &[Event],
+    ) -> Result<()> {
+        for chunk in events.chunks(BULK_ROWS) {
+            let mut builder = QueryBuilder::new(
+                "insert into events(id, timestamp, hostname, event_type, full_path, from_path) ",
+            );
+            builder.push_values(chunk, |mut row, e| {
+                let event_type = match e.event_type {
+                    EventType::Create => "create",
+                    EventType::Delete => "delete",
+                    EventType::Modify => "modify",
+                    EventType::Rename => "rename",
+                };
+                row.push_bind(e.id.as_str())
+                    .push_bind(e.timestamp.timestamp_nanos_opt().unwrap_or_default())
+                    .push_bind(e.hostname.as_str())
+                    .push_bind(event_type)
+                    .push_bind(e.full_path.as_str())
+                    .push_bind(e.from_path.as_deref());
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn save_raw(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, f: &File) -> Result<()> {
+        Self::save_rows(tx, &[f]).await?;
+        Ok(())
+    }
+
+    /// insert or update `files`, which are either all on a known volume or all on none, as
+    /// those conflict on different keys. returns the events of the new and changed ones, a
+    /// file seen again as it was is none.
+    async fn save_rows(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        files: &[&File],
+    ) -> Result<(Saved, Vec<Event>)> {
+        let mut saved = Saved::default();
+        let mut events = vec![];
+        let Some(first) = files.first() else {
+            return Ok((saved, events));
         };
+        // a file on a known volume is the same file wherever the volume is mounted.
+        let conflict = if first.volume_id.is_some() {
+            "(volume_id, rel_path) where volume_id is not null"
+        } else {
+            "(full_path) where volume_id is null"
+        };
+
+        for chunk in files.chunks(BULK_ROWS) {
+            let mut builder = QueryBuilder::new(
+                "insert into file(id, timestamp, full_path, search_path, file_name, dir, hostname, size, modified, created, device, inode, tokens, pinyin, volume_id, rel_path, last_seen, changed, partial_hash, hash) ",
+            );
+            builder.push_values(chunk, |mut row, f| {
+                row.push_bind(f.id.as_str())
+                    .push_bind(f.timestamp.timestamp_nanos_opt().unwrap_or_default())
+                    .push_bind(f.full_path.as_str())
+                    .push_bind(normalize(&f.full_path))
+                    .push_bind(f.file_name.as_str())
+                    .push_bind(f.dir)
+                    .push_bind(f.hostname.as_str())
+                    .push_bind(f.size)
+                    .push_bind(f.modified.and_then(|t| t.timestamp_nanos_opt()))
+                    .push_bind(f.created.and_then(|t| t.timestamp_nanos_opt()))
+                    .push_bind(f.device)
+                    .push_bind(f.inode)
+                    .push_bind(f.tokens.as_str())
+                    .push_bind(f.pinyin.as_str())
+                    .push_bind(f.volume_id.as_deref())
+                    .push_bind(f.rel_path.as_str())
+                    .push_bind(f.last_seen.timestamp_nanos_opt().unwrap_or_default())
+                    .push_bind(f.last_seen.timestamp_nanos_opt().unwrap_or_default())
+                    .push_bind(f.partial_hash.as_deref())
+                    .push_bind(f.hash.as_deref());
+            });
+            builder.push(format!(
+                " on conflict{conflict} do update set full_path = excluded.full_path, search_path = excluded.search_path,
+                 file_name = excluded.file_name,
+                 dir = excluded.dir, hostname = excluded.hostname, size = excluded.size, modified = excluded.modified,
+                 created = excluded.created, device = excluded.device, inode = excluded.inode, tokens = excluded.tokens,
+                 pinyin = excluded.pinyin, last_seen = excluded.last_seen,
+                 changed = case when file.size = excluded.size and file.modified is excluded.modified
+                     then file.changed else excluded.changed end,
+                 partial_hash = case when file.size = excluded.size and file.modified is excluded.modified
+                     then coalesce(excluded.partial_hash, file.partial_hash) else excluded.partial_hash end,
+                 hash = case when file.size = excluded.size and file.modified is excluded.modified
+                     then coalesce(excluded.hash, file.hash) else excluded.hash end
+                 returning id, full_path, changed = last_seen"
+            ));
+            // a known file keeps its id, a new one has the id it was given.
+            let by_path: HashMap<&str, &File> =
+                chunk.iter().map(|f| (f.full_path.as_str(), *f)).collect();
+            let rows: Vec<(String, String, bool)> =
+                builder.build_query_as().fetch_all(&mut *tx).await?;
+            for (id, path, changed) in rows {
+                let Some(f) = by_path.get(path.as_str()) else {
+                    continue;
+                };
+                if id == f.id {
+                    saved.new += 1;
+                    events.push(Event::new_create(f));
+                } else if changed {
+                    saved.updated += 1;
+                    events.push(Event::new_modify(f));
+                }
+            }
+        }
 
-        sqlx::query("insert or ignore into events(id, timestamp, hostname, event_type, full_path) values(?1, ?2, ?3, ?4, ?5)")
-            .bind(e.id.as_str())
-            .bind(e.timestamp.timestamp_nanos())
-            .bind(e.hostname.as_str())
-            .bind(event_type)
-            .bind(e.full_path.as_str())
+        Ok((saved, events))
+    }
+
+    async fn delete_raw(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, id: &str) -> Result<()> {
+        sqlx::query("delete from file where id = ?1")
+            .bind(id)
             .execute(tx)
             .await?;
 
         Ok(())
     }
 
-    async fn save_raw(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, f: &File) -> Result<()> {
+    /// ids and paths of the file at `path` and everything under it.
+    async fn paths_under(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        path: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let path = path.trim_end_matches('/');
+        let res = sqlx::query_as(
+            "select id, full_path from file
+                 where full_path = ?1 or substr(full_path, 1, length(?2)) = ?2",
+        )
+        .bind(path)
+        .bind(format!("{path}/"))
+        .fetch_all(tx)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// the whole query for `q`: name, filters, order, limit and offset.
+    fn search_builder(q: &SearchQuery) -> QueryBuilder<'_, sqlx::Sqlite> {
+        let column = if q.full_path {
+            "search_path"
+        } else {
+            "file_name"
+        };
+        let mut builder = match &q.mode {
+            SearchMode::Substring => {
+                let mut builder = QueryBuilder::new("select * from file where 1 = 1");
+                for term in tokenize(&fold(&q.name)) {
+                    let pattern = format!("%{}%", escape_like(&term));
+                    if q.full_path {
+                        // like only folds the case of ascii, the terms are lower case.
+                        builder
+                            .push(" and search_path regexp ")
+                            .push_bind(format!("(?i){}", regex::escape(&term)));
+                    } else {
+                        builder
+                            .push(" and (tokens like ")
+                            .push_bind(pattern.clone())
+                            .push(" escape '\\' or pinyin like ")
+                            .push_bind(pattern)
+                            .push(" escape '\\')");
+                    }
+                }
+                builder
+            }
+            SearchMode::FullText => {
+                let mut builder = QueryBuilder::new(
+                    "select file.* from file_fts join file on file.rowid = file_fts.rowid where file_fts match ",
+                );
+                builder.push_bind(q.name.as_str());
+                builder
+            }
+            SearchMode::Glob => {
+                let mut builder = QueryBuilder::new("select * from file where ");
+                builder
+                    .push(column)
+                    .push(" glob ")
+                    .push_bind(q.name.as_str());
+                builder
+            }
+            SearchMode::Regex(re) => {
+                let mut builder = QueryBuilder::new("select * from file where ");
+                builder.push(column).push(" regexp ").push_bind(re.as_str());
+                builder
+            }
+        };
+        Self::push_filters(&mut builder, q);
+
+        let order = match q.sort {
+            Some(Sort::Name) => "file.file_name",
+            Some(Sort::Path) => "file.full_path",
+            Some(Sort::Size) => "file.size",
+            Some(Sort::Mtime) => "file.modified",
+            Some(Sort::Scanned) => "file.timestamp",
+            // a hit in the name weighs more than one somewhere in the path.
+            None if matches!(q.mode, SearchMode::FullText) => "bm25(file_fts, 10.0, 1.0)",
+            None => "file.rowid",
+        };
+        let direction = if q.reverse { "desc" } else { "asc" };
+        builder
+            .push(" order by ")
+            .push(order)
+            .push(" ")
+            .push(direction)
+            .push(", file.full_path ")
+            .push(direction);
+
+        builder
+            .push(" limit ")
+            // a negative limit is none at all to sqlite.
+            .push_bind(q.limit.map_or(-1, i64::from))
+            .push(" offset ")
+            .push_bind(q.offset.unwrap_or(0));
+
+        builder
+    }
+
+    /// and the filters of `q` besides the name onto a query over `file`.
+    fn push_filters<'a>(builder: &mut QueryBuilder<'a, sqlx::Sqlite>, q: &'a SearchQuery) {
+        match q.kind {
+            Some(Kind::Dir) => builder.push(" and file.dir = 1"),
+            Some(Kind::File) => builder.push(" and file.dir = 0"),
+            None => builder,
+        };
+
+        if !q.extensions.is_empty() {
+            builder.push(" and (0");
+            for ext in &q.extensions {
+                builder
+                    .push(" or file.file_name like ")
+                    .push_bind(format!("%.{}", escape_like(ext)))
+                    .push(" escape '\\'");
+            }
+            builder.push(")");
+        }
+
+        if let Some(size) = q.min_size {
+            builder.push(" and file.size >= ").push_bind(size);
+        }
+        if let Some(size) = q.max_size {
+            builder.push(" and file.size <= ").push_bind(size);
+        }
+
+        let times = [
+            ("file.modified >= ", q.modified_after),
+            ("file.modified < ", q.modified_before),
+            ("file.timestamp >= ", q.scanned_after),
+            ("file.timestamp < ", q.scanned_before),
+        ];
+        for (column, time) in times {
+            if let Some(t) = time.and_then(|t| t.timestamp_nanos_opt()) {
+                builder.push(" and ").push(column).push_bind(t);
+            }
+        }
+
+        if let Some(hostname) = &q.hostname {
+            builder
+                .push(" and (file.hostname = ")
+                .push_bind(hostname.as_str())
+                .push(" or file.hostname like ")
+                .push_bind(format!("{}:%", escape_like(hostname)))
+                .push(" escape '\\')");
+        }
+
+        if let Some(prefix) = &q.path_prefix {
+            let prefix = normalize(prefix.trim_end_matches('/'));
+            builder
+                .push(" and (file.search_path = ")
+                .push_bind(prefix.clone())
+                .push(" or substr(file.search_path, 1, length(")
+                .push_bind(format!("{prefix}/"))
+                .push(")) = ")
+                .push_bind(format!("{prefix}/"))
+                .push(")");
+        }
+
+        let media = [
+            ("width >= ", q.min_width),
+            ("height >= ", q.min_height),
+            ("duration_ms >= ", q.min_duration_ms),
+            ("duration_ms <= ", q.max_duration_ms),
+        ];
+        let codecs = [
+            ("video_codec = ", &q.video_codec),
+            ("audio_codec = ", &q.audio_codec),
+        ];
+        if media.iter().any(|(_, v)| v.is_some()) || codecs.iter().any(|(_, v)| v.is_some()) {
+            builder.push(" and file.id in (select file_id from media_info where 1 = 1");
+            for (column, value) in media {
+                if let Some(v) = value {
+                    builder.push(" and ").push(column).push_bind(v);
+                }
+            }
+            for (column, value) in codecs {
+                if let Some(v) = value {
+                    builder.push(" and ").push(column).push_bind(v.as_str());
+                }
+            }
+            builder.push(")");
+        }
+
+        let languages = [
+            (TrackKind::Audio, &q.audio_lang),
+            (TrackKind::Subtitle, &q.subtitle_lang),
+        ];
+        for (kind, lang) in languages {
+            let Some(lang) = lang else {
+                continue;
+            };
+            builder
+                .push(" and file.id in (select file_id from tracks where kind = ")
+                .push_bind(kind.as_str())
+                .push(" and language in (");
+            let mut codes = builder.separated(", ");
+            for code in language_codes(lang) {
+                codes.push_bind(code);
+            }
+            builder.push("))");
+        }
+
+        let tags = [
+            (&["artist", "album_artist"][..], &q.artist),
+            (&["album"][..], &q.album),
+            (&["genre"][..], &q.genre),
+        ];
+        for (columns, value) in tags {
+            let Some(value) = value else {
+                continue;
+            };
+            let pattern = format!("%{}%", escape_like(value));
+            builder.push(" and file.id in (select file_id from audio_tags where ");
+            let mut any = builder.separated(" or ");
+            for column in columns {
+                any.push(format!("{column} like "))
+                    .push_bind_unseparated(pattern.clone())
+                    .push_unseparated(" escape '\\'");
+            }
+            builder.push(")");
+        }
+        if let Some(title) = &q.title {
+            let pattern = format!("%{}%", escape_like(title));
+            builder
+                .push(" and file.id in (select file_id from audio_tags where title like ")
+                .push_bind(pattern.clone())
+                .push(" escape '\\' union select file_id from media_info where title like ")
+                .push_bind(pattern)
+                .push(" escape '\\')");
+        }
+        if let Some(year) = q.year {
+            builder
+                .push(" and file.id in (select file_id from audio_tags where year = ")
+                .push_bind(year)
+                .push(")");
+        }
+    }
+
+    /// files under `root` seen since `since` that also match `condition`.
+    async fn seen_files(
+        &self,
+        root: &str,
+        since: DateTime<Utc>,
+        condition: &str,
+    ) -> Result<Vec<File>> {
+        let sql = format!(
+            "select * from file where dir = 0 and substr(full_path, 1, length(?1)) = ?1
+                 and last_seen >= ?2 and {condition} order by full_path"
+        );
+        sqlx::query(&sql)
+            .bind(format!("{}/", root.trim_end_matches('/')))
+            .bind(since.timestamp_nanos_opt().unwrap_or_default())
+            .map(Self::query_file)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// files that `condition` holds for, with a partner `partner` holds for, where one of the
+    /// two is under `root` and seen since `since`. a copy of a new file left in another root
+    /// would never be hashed otherwise, until that root is scanned again.
+    async fn hash_candidates(
+        &self,
+        root: &str,
+        since: DateTime<Utc>,
+        condition: &str,
+        partner: &str,
+    ) -> Result<Vec<File>> {
+        let seen = |t: &str| {
+            format!("(substr({t}.full_path, 1, length(?1)) = ?1 and {t}.last_seen >= ?2)")
+        };
+        let sql = format!(
+            "select * from file where dir = 0 and {condition} and exists (select 1 from file other
+                 where other.dir = 0 and other.id != file.id and {partner}
+                 and ({} or {})) order by full_path",
+            seen("file"),
+            seen("other")
+        );
+        sqlx::query(&sql)
+            .bind(format!("{}/", root.trim_end_matches('/')))
+            .bind(since.timestamp_nanos_opt().unwrap_or_default())
+            .map(Self::query_file)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    fn query_volume(row: SqliteRow) -> Volume {
+        Volume {
+            id: row.get("id"),
+            uuid: row.get("uuid"),
+            label: row.get("label"),
+            fs_type: row.get("fs_type"),
+            mount_point: row.get("mount_point"),
+            hostname: row.get("hostname"),
+            last_seen: Utc.timestamp_nanos(row.get("last_seen")),
+        }
+    }
+
+    /// insert the row of a scan, or bring it up to date.
+    async fn save_scan(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, r: &ScanReport) -> Result<()> {
         sqlx::query(
-            "insert or ignore into file(id, timestamp, full_path, file_name, dir, hostname)
-                 values(?1, ?2, ?3, ?4, ?5, ?6)",
+            "insert into scans(id, root, hostname, started, finished, entries, new, updated, removed, errors, complete)
+                 values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                 on conflict(id) do update set finished = excluded.finished, entries = excluded.entries,
+                 new = excluded.new, updated = excluded.updated, removed = excluded.removed,
+                 errors = excluded.errors, complete = excluded.complete",
         )
-        .bind(f.id.as_str())
-        .bind(f.timestamp.timestamp_nanos())
-        .bind(f.full_path.as_str())
-        .bind(f.file_name.as_str())
-        .bind(f.dir)
-        .bind(f.hostname.as_str())
+        .bind(r.id.as_str())
+        .bind(r.root.as_str())
+        .bind(r.hostname.as_str())
+        .bind(r.started.timestamp_nanos_opt().unwrap_or_default())
+        .bind(r.finished.timestamp_nanos_opt().unwrap_or_default())
+        .bind(r.entries as i64)
+        .bind(r.new as i64)
+        .bind(r.updated as i64)
+        .bind(r.removed as i64)
+        .bind(r.errored as i64)
+        .bind(r.complete)
         .execute(tx)
         .await?;
 
         Ok(())
     }
 
+    fn query_scan(row: SqliteRow) -> ScanReport {
+        ScanReport {
+            id: row.get("id"),
+            root: row.get("root"),
+            hostname: row.get("hostname"),
+            started: Utc.timestamp_nanos(row.get("started")),
+            finished: Utc.timestamp_nanos(row.get("finished")),
+            entries: row.get::<i64, _>("entries") as u64,
+            new: row.get::<i64, _>("new") as u64,
+            updated: row.get::<i64, _>("updated") as u64,
+            removed: row.get::<i64, _>("removed") as u64,
+            errored: row.get::<i64, _>("errors") as u64,
+            errors: vec![],
+            complete: row.get("complete"),
+        }
+    }
+
     fn query_file(row: SqliteRow) -> File {
         File {
             id: row.get("id"),
@@ -99,6 +698,22 @@ impl Sqlite {
             file_name: row.get("file_name"),
             dir: row.get("dir"),
             hostname: row.get("hostname"),
+            size: row.get("size"),
+            modified: row
+                .get::<Option<i64>, _>("modified")
+                .map(|t| Utc.timestamp_nanos(t)),
+            created: row
+                .get::<Option<i64>, _>("created")
+                .map(|t| Utc.timestamp_nanos(t)),
+            device: row.get("device"),
+            inode: row.get("inode"),
+            tokens: row.get("tokens"),
+            pinyin: row.get("pinyin"),
+            volume_id: row.get("volume_id"),
+            rel_path: row.get("rel_path"),
+            last_seen: Utc.timestamp_nanos(row.get("last_seen")),
+            hash: row.get("hash"),
+            partial_hash: row.get("partial_hash"),
         }
     }
 }
@@ -107,43 +722,60 @@ impl Sqlite {
 impl Database for Sqlite {
     async fn save(&mut self, f: &File) -> Result<()> {
         // debug!("saving file to sqlite");
-        let event = Event::new_create(f);
-
         let mut tx = self.pool.begin().await?;
-        Self::save_raw(&mut tx, f).await?;
-        Self::save_event(&mut tx, &event).await?;
+        let (_, events) = Self::save_rows(&mut tx, &[f]).await?;
+        Self::save_events(&mut tx, &events).await?;
         tx.commit().await?;
 
         Ok(())
     }
 
-    async fn save_bulk(&mut self, f: &[File]) -> Result<()> {
-        debug!("saving file to sqlite on bulk.");
+    async fn save_bulk(&mut self, f: &[File]) -> Result<Saved> {
+        trace!("saving {} files to sqlite on bulk.", f.len());
 
+        let (on_volume, on_none): (Vec<&File>, Vec<&File>) =
+            f.iter().partition(|f| f.volume_id.is_some());
         let mut tx = self.pool.begin().await?;
-        for i in f {
-            let event = Event::new_create(i);
-            Self::save_raw(&mut tx, i).await?;
-            Self::save_event(&mut tx, &event).await?;
-        }
-
+        let (a, mut events) = Self::save_rows(&mut tx, &on_volume).await?;
+        let (b, more) = Self::save_rows(&mut tx, &on_none).await?;
+        events.extend(more);
+        Self::save_events(&mut tx, &events).await?;
         tx.commit().await?;
 
-        Ok(())
+        Ok(Saved {
+            new: a.new + b.new,
+            updated: a.updated + b.updated,
+        })
     }
 
     async fn update(&self, f: &File) -> Result<()> {
-        debug!("updating sqlite file.");
+        trace!("updating sqlite file.");
         sqlx::query(
-            "update file set timestamp = ?2, full_path= ?3, file_name = ?4, hostname = ?5 where id = ?1",
+            "update file set timestamp = ?2, full_path= ?3, file_name = ?4, hostname = ?5,
+                size = ?6, modified = ?7, created = ?8, device = ?9, inode = ?10, tokens = ?11,
+                pinyin = ?12, volume_id = ?13, rel_path = ?14, last_seen = ?15,
+                partial_hash = ?16, hash = ?17, search_path = ?18 where id = ?1",
         )
-            .bind(f.id.as_str())
-            .bind(f.timestamp.timestamp_nanos())
-            .bind(f.full_path.as_str())
-            .bind(f.file_name.as_str())
-            .bind(f.hostname.as_str())
-            .execute(&self.pool)
-            .await?;
+        .bind(f.id.as_str())
+        .bind(f.timestamp.timestamp_nanos_opt().unwrap_or_default())
+        .bind(f.full_path.as_str())
+        .bind(f.file_name.as_str())
+        .bind(f.hostname.as_str())
+        .bind(f.size)
+        .bind(f.modified.and_then(|t| t.timestamp_nanos_opt()))
+        .bind(f.created.and_then(|t| t.timestamp_nanos_opt()))
+        .bind(f.device)
+        .bind(f.inode)
+        .bind(f.tokens.as_str())
+        .bind(f.pinyin.as_str())
+        .bind(f.volume_id.as_deref())
+        .bind(f.rel_path.as_str())
+        .bind(f.last_seen.timestamp_nanos_opt().unwrap_or_default())
+        .bind(f.partial_hash.as_deref())
+        .bind(f.hash.as_deref())
+        .bind(normalize(&f.full_path))
+        .execute(&self.pool)
+        .await?;
 
         Ok(())
     }
@@ -164,32 +796,472 @@ impl Database for Sqlite {
         Ok(res)
     }
 
-    async fn query_file(&self, query: &str) -> Result<Vec<File>> {
-        let res: Vec<File> = sqlx::query(query)
-            .map(Self::query_file)
+    fn search<'a>(&'a self, q: &'a SearchQuery) -> BoxStream<'a, Result<File>> {
+        Box::pin(try_stream! {
+            let mut builder = Self::search_builder(q);
+            let mut rows = builder.build().map(Self::query_file).fetch(&self.pool);
+            while let Some(f) = rows.try_next().await? {
+                yield f;
+            }
+        })
+    }
+
+    async fn remove_stale(
+        &mut self,
+        root: &str,
+        volumes: &[Volume],
+        since: DateTime<Utc>,
+        keep: &[String],
+        filter: &FileFilter,
+    ) -> Result<Vec<String>> {
+        let prefix = format!("{}/", root.trim_end_matches('/'));
+        let since = since.timestamp_nanos_opt().unwrap_or_default();
+
+        let mut tx = self.pool.begin().await?;
+        let mut stale: Vec<(String, String, String, bool)> = sqlx::query_as(
+            "select id, full_path, file_name, dir from file where volume_id is null and hostname = ?3
+                 and substr(full_path, 1, length(?1)) = ?1 and last_seen < ?2",
+        )
+        .bind(prefix.as_str())
+        .bind(since)
+        .bind(hostname())
+        .fetch_all(&mut tx)
+        .await?;
+
+        // files on a volume are matched by `rel_path`, they may still carry an older mount point.
+        for v in volumes {
+            let prefix = match v.relative(root) {
+                Some(rel) if !rel.is_empty() => format!("{rel}/"),
+                // root is the mount point or above it, the whole volume was walked.
+                _ => String::new(),
+            };
+            let paths: Vec<(String, String, String, bool)> = sqlx::query_as(
+                "select id, full_path, file_name, dir from file where volume_id = ?1
+                     and substr(rel_path, 1, length(?2)) = ?2 and last_seen < ?3",
+            )
+            .bind(v.id.as_str())
+            .bind(prefix.as_str())
+            .bind(since)
+            .fetch_all(&mut tx)
+            .await?;
+            stale.extend(paths);
+        }
+        stale.retain(|(_, p, name, dir)| {
+            (*dir || filter.matches(name))
+                && !keep
+                    .iter()
+                    .any(|k| p == k || p.starts_with(&format!("{}/", k.trim_end_matches('/'))))
+        });
+
+        for (id, p, _, _) in &stale {
+            let event = Event::new_delete(p);
+            Self::delete_raw(&mut tx, id).await?;
+            Self::save_event(&mut tx, &event).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(stale.into_iter().map(|(_, p, _, _)| p).collect())
+    }
+
+    async fn save_modified(&mut self, f: &File) -> Result<()> {
+        let event = Event::new_modify(f);
+
+        let mut tx = self.pool.begin().await?;
+        Self::save_raw(&mut tx, f).await?;
+        Self::save_event(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn rename(&mut self, from: &str, to: &File) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let known: Option<(String, String)> = sqlx::query_as(
+            "select id, rel_path from file where full_path = ?1 and volume_id is ?2",
+        )
+        .bind(from)
+        .bind(to.volume_id.as_deref())
+        .fetch_optional(&mut tx)
+        .await?;
+        let Some((id, from_rel)) = known else {
+            return Ok(false);
+        };
+
+        // whatever was at `to` was replaced.
+        let replaced = Self::paths_under(&mut tx, &to.full_path).await?;
+        for (other, p) in replaced.iter().filter(|(other, _)| *other != id) {
+            Self::delete_raw(&mut tx, other).await?;
+            Self::save_event(&mut tx, &Event::new_delete(p)).await?;
+        }
+
+        sqlx::query(
+            "update file set full_path = ?3 || substr(full_path, length(?1) + 1),
+                 rel_path = ?4 || substr(rel_path, length(?2) + 1)
+                 where full_path = ?1 or substr(full_path, 1, length(?1) + 1) = ?1 || '/'",
+        )
+        .bind(from)
+        .bind(from_rel.as_str())
+        .bind(to.full_path.as_str())
+        .bind(to.rel_path.as_str())
+        .execute(&mut tx)
+        .await?;
+        let moved = Self::paths_under(&mut tx, &to.full_path).await?;
+        Self::save_search_paths(&mut tx, &moved).await?;
+        // the new name and tokens, the row keeps its id.
+        Self::save_raw(&mut tx, to).await?;
+        Self::save_event(&mut tx, &Event::new_rename(from, to)).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn remove(&mut self, path: &str) -> Result<Vec<String>> {
+        let mut tx = self.pool.begin().await?;
+        let removed = Self::paths_under(&mut tx, path).await?;
+        for (id, p) in &removed {
+            Self::delete_raw(&mut tx, id).await?;
+            Self::save_event(&mut tx, &Event::new_delete(p)).await?;
+        }
+        tx.commit().await?;
+
+        Ok(removed.into_iter().map(|(_, p)| p).collect())
+    }
+
+    async fn save_volume(&mut self, v: &Volume) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let moved: Option<String> = sqlx::query_scalar(
+            "select mount_point from volumes where id = ?1 and mount_point != ?2",
+        )
+        .bind(v.id.as_str())
+        .bind(v.mount_point.as_str())
+        .fetch_optional(&mut tx)
+        .await?;
+
+        sqlx::query(
+            "insert into volumes(id, uuid, label, fs_type, mount_point, hostname, last_seen)
+                 values(?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 on conflict(id) do update set label = excluded.label, fs_type = excluded.fs_type,
+                 mount_point = excluded.mount_point, hostname = excluded.hostname, last_seen = excluded.last_seen",
+        )
+        .bind(v.id.as_str())
+        .bind(v.uuid.as_deref())
+        .bind(v.label.as_deref())
+        .bind(v.fs_type.as_deref())
+        .bind(v.mount_point.as_str())
+        .bind(v.hostname.as_str())
+        .bind(v.last_seen.timestamp_nanos_opt().unwrap_or_default())
+        .execute(&mut tx)
+        .await?;
+
+        if let Some(from) = moved {
+            debug!("volume {} moved from {from} to {}", v.name(), v.mount_point);
+            sqlx::query(
+                "update file set full_path = case when rel_path = '' then ?2
+                     else rtrim(?2, '/') || '/' || rel_path end where volume_id = ?1",
+            )
+            .bind(v.id.as_str())
+            .bind(v.mount_point.as_str())
+            .execute(&mut tx)
+            .await?;
+            let moved: Vec<(String, String)> =
+                sqlx::query_as("select id, full_path from file where volume_id = ?1")
+                    .bind(v.id.as_str())
+                    .fetch_all(&mut tx)
+                    .await?;
+            Self::save_search_paths(&mut tx, &moved).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn volumes(&self) -> Result<Vec<Volume>> {
+        let res = sqlx::query("select * from volumes")
+            .map(Self::query_volume)
             .fetch_all(&self.pool)
             .await?;
 
         Ok(res)
     }
+
+    async fn partial_hash_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>> {
+        self.hash_candidates(
+            root,
+            since,
+            "partial_hash is null",
+            "other.size = file.size",
+        )
+        .await
+    }
+
+    async fn full_hash_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>> {
+        self.hash_candidates(
+            root,
+            since,
+            "hash is null and partial_hash is not null",
+            "other.size = file.size and other.partial_hash = file.partial_hash",
+        )
+        .await
+    }
+
+    async fn save_hash(&mut self, f: &File) -> Result<()> {
+        sqlx::query("update file set partial_hash = ?2, hash = ?3 where id = ?1")
+            .bind(f.id.as_str())
+            .bind(f.partial_hash.as_deref())
+            .bind(f.hash.as_deref())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn dupes(&self, min_size: i64) -> Result<Vec<Vec<File>>> {
+        let files = sqlx::query(
+            "select * from file where hash in (select hash from file where hash is not null
+                 and dir = 0 and size >= ?1 group by hash having count(1) > 1)
+                 order by size desc, hash, full_path",
+        )
+        .bind(min_size)
+        .map(Self::query_file)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut groups: Vec<Vec<File>> = vec![];
+        for f in files {
+            match groups.last_mut() {
+                Some(group) if group[0].hash == f.hash => group.push(f),
+                _ => groups.push(vec![f]),
+            }
+        }
+
+        Ok(groups)
+    }
+
+    async fn media_info_candidates(&self, root: &str, since: DateTime<Utc>) -> Result<Vec<File>> {
+        // the rest would never get a row, and be picked up again by every scan.
+        let readable: Vec<String> = readable_extensions()
+            .map(|e| format!("file_name like '%.{e}'"))
+            .collect();
+        let condition = format!(
+            "({}) and not exists (select 1 from media_info where file_id = file.id)",
+            readable.join(" or ")
+        );
+        self.seen_files(root, since, &condition).await
+    }
+
+    async fn save_media_info(&mut self, m: &MediaInfo) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "insert into media_info(file_id, duration_ms, width, height, video_codec, audio_codec, created, title)
+                 values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 on conflict(file_id) do update set duration_ms = excluded.duration_ms, width = excluded.width,
+                 height = excluded.height, video_codec = excluded.video_codec,
+                 audio_codec = excluded.audio_codec, created = excluded.created, title = excluded.title",
+        )
+        .bind(m.file_id.as_str())
+        .bind(m.duration_ms)
+        .bind(m.width)
+        .bind(m.height)
+        .bind(m.video_codec.as_deref())
+        .bind(m.audio_codec.as_deref())
+        .bind(m.created.and_then(|t| t.timestamp_nanos_opt()))
+        .bind(m.title.as_deref())
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query("delete from tracks where file_id = ?1")
+            .bind(m.file_id.as_str())
+            .execute(&mut tx)
+            .await?;
+        for t in &m.tracks {
+            sqlx::query(
+                "insert or replace into tracks(file_id, number, kind, codec, language, name)
+                     values(?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(m.file_id.as_str())
+            .bind(t.number)
+            .bind(t.kind.as_str())
+            .bind(t.codec.as_deref())
+            .bind(t.language.as_deref())
+            .bind(t.name.as_deref())
+            .execute(&mut tx)
+            .await?;
+        }
+
+        match &m.tags {
+            Some(t) => {
+                sqlx::query(
+                    "insert or replace into audio_tags(file_id, title, artist, album, album_artist, genre, track, year)
+                         values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )
+                .bind(m.file_id.as_str())
+                .bind(t.title.as_deref())
+                .bind(t.artist.as_deref())
+                .bind(t.album.as_deref())
+                .bind(t.album_artist.as_deref())
+                .bind(t.genre.as_deref())
+                .bind(t.track)
+                .bind(t.year)
+                .execute(&mut tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("delete from audio_tags where file_id = ?1")
+                    .bind(m.file_id.as_str())
+                    .execute(&mut tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn save_report(&mut self, r: &ScanReport) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::save_scan(&mut tx, r).await?;
+        sqlx::query("delete from scan_errors where scan_id = ?1")
+            .bind(r.id.as_str())
+            .execute(&mut tx)
+            .await?;
+        for chunk in r.errors.chunks(BULK_ROWS) {
+            let mut builder = QueryBuilder::new(
+                "insert into scan_errors(scan_id, root, hostname, started, path, kind, message) ",
+            );
+            builder.push_values(chunk, |mut row, e| {
+                row.push_bind(r.id.as_str())
+                    .push_bind(r.root.as_str())
+                    .push_bind(r.hostname.as_str())
+                    .push_bind(r.started.timestamp_nanos_opt().unwrap_or_default())
+                    .push_bind(e.path.as_str())
+                    .push_bind(e.kind.as_str())
+                    .push_bind(e.message.as_str());
+            });
+            builder.build().execute(&mut tx).await?;
+        }
+        if r.complete {
+            sqlx::query("delete from scan_checkpoints where scan_id = ?1")
+                .bind(r.id.as_str())
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn save_checkpoints(&mut self, r: &ScanReport, dirs: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::save_scan(&mut tx, r).await?;
+        for chunk in dirs.chunks(BULK_ROWS) {
+            let mut builder =
+                QueryBuilder::new("insert or ignore into scan_checkpoints(scan_id, path) ");
+            builder.push_values(chunk, |mut row, dir| {
+                row.push_bind(r.id.as_str()).push_bind(dir.as_str());
+            });
+            builder.build().execute(&mut tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn unfinished_scan(&self, root: &str, hostname: &str) -> Result<Option<ScanReport>> {
+        let last = sqlx::query(
+            "select * from scans where root = ?1 and hostname = ?2 order by started desc limit 1",
+        )
+        .bind(root)
+        .bind(hostname)
+        .map(Self::query_scan)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(last.filter(|r| !r.complete))
+    }
+
+    async fn checkpoints(&self, scan_id: &str) -> Result<Vec<String>> {
+        sqlx::query_scalar("select path from scan_checkpoints where scan_id = ?1")
+            .bind(scan_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn scans(&self, root: Option<&str>, limit: i64) -> Result<Vec<ScanReport>> {
+        sqlx::query(
+            "select * from scans where ?1 is null or root = ?1 order by started desc limit ?2",
+        )
+        .bind(root)
+        .bind(limit)
+        .map(Self::query_scan)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn scan_report(&self, id: &str) -> Result<Option<ScanReport>> {
+        let Some(mut report) = sqlx::query(
+            "select * from scans where substr(id, 1, length(?1)) = ?1 order by started desc",
+        )
+        .bind(id)
+        .map(Self::query_scan)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        report.errors = sqlx::query(
+            "select path, kind, message from scan_errors where scan_id = ?1 order by id",
+        )
+        .bind(report.id.as_str())
+        .map(|row: SqliteRow| ScanError {
+            path: row.get("path"),
+            kind: ErrorKind::parse(row.get("kind")),
+            message: row.get("message"),
+        })
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(report))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::log::log_init;
+    use crate::media::Category;
+    use crate::media_info::{Tags, Track};
     use crate::util::uuid_v4;
+    use regex::Regex;
 
     async fn db_save(db: &mut impl Database, f: &File) -> Result<()> {
         db.save(f).await
     }
 
-    async fn db_query_file(db: &impl Database, query: &str) -> Result<()> {
-        let results = db.query_file(query).await.unwrap();
+    async fn db_search(db: &impl Database, q: &SearchQuery) -> Result<()> {
+        let results: Vec<File> = db.search(q).try_collect().await.unwrap();
         debug!("results:{:#?}", results);
         Ok(())
     }
 
+    async fn paths_under(db: &impl Database, prefix: &str) -> Vec<String> {
+        let q = SearchQuery {
+            path_prefix: Some(prefix.to_string()),
+            ..Default::default()
+        };
+        let mut found = db
+            .search(&q)
+            .map_ok(|f| f.full_path)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        found.sort();
+        found
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_db() {
         log_init();
@@ -200,7 +1272,19 @@ mod test {
             hostname: "liweideMacBook-Pro.local".to_string(),
             full_path: "/Users/liwei/coding/rust/tools/find_videos".to_string(),
             file_name: "find_videos".to_string(),
+            tokens: "find videos".to_string(),
+            pinyin: "".to_string(),
             dir: true,
+            size: 4096,
+            modified: Some(Utc::now()),
+            created: None,
+            device: 0,
+            inode: 0,
+            volume_id: None,
+            rel_path: "/Users/liwei/coding/rust/tools/find_videos".to_string(),
+            last_seen: Utc::now(),
+            hash: None,
+            partial_hash: None,
         };
 
         let f2 = File {
@@ -209,13 +1293,1146 @@ mod test {
             hostname: "liweideMacBook-Pro.local".to_string(),
             full_path: "/Users/liwei/coding/rust/go语言基础".to_string(),
             file_name: "go语言基础".to_string(),
+            tokens: "go语言基础".to_string(),
+            pinyin: "goyuyanjichu goyyjc".to_string(),
             dir: false,
+            size: 2 * 1024 * 1024,
+            modified: Some(Utc::now()),
+            created: Some(Utc::now()),
+            device: 0,
+            inode: 0,
+            volume_id: None,
+            rel_path: "/Users/liwei/coding/rust/go语言基础".to_string(),
+            last_seen: Utc::now(),
+            hash: None,
+            partial_hash: None,
         };
 
         db_save(&mut db, &f).await.unwrap();
         db_save(&mut db, &f2).await.unwrap();
 
-        let query = format!("select * from file where file_name like '%go%';");
-        db_query_file(&db, &query).await.unwrap();
+        let q = SearchQuery {
+            name: "go".to_string(),
+            ..Default::default()
+        };
+        db_search(&db, &q).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let mut f = File::new(
+            format!("{root}/old.mkv"),
+            "old.mkv".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &f).await.unwrap();
+
+        f.file_name = "新.mkv".to_string();
+        f.tokens = "新 mkv".to_string();
+        f.pinyin = "xin x".to_string();
+        let mut v = Volume::detect(Path::new("Cargo.toml")).unwrap();
+        v.id = uuid_v4();
+        db.save_volume(&v).await.unwrap();
+        f.volume_id = Some(v.id.clone());
+        f.rel_path = "新.mkv".to_string();
+        db.update(&f).await.unwrap();
+        let q = SearchQuery {
+            path_prefix: Some(root),
+            ..Default::default()
+        };
+        let found: Vec<File> = db.search(&q).try_collect().await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, f.id);
+        assert_eq!(
+            (
+                found[0].file_name.as_str(),
+                found[0].tokens.as_str(),
+                found[0].pinyin.as_str()
+            ),
+            ("新.mkv", "新 mkv", "xin x")
+        );
+        assert_eq!(
+            (found[0].volume_id.as_deref(), found[0].rel_path.as_str()),
+            (Some(v.id.as_str()), "新.mkv")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fill_pinyin() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        // saved before there was pinyin.
+        let mut f = File::new(
+            format!("{root}/语言.mkv"),
+            "语言.mkv".to_string(),
+            false,
+            None,
+        );
+        f.pinyin = String::new();
+        db_save(&mut db, &f).await.unwrap();
+
+        sqlx::query("pragma user_version = 0")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        Sqlite::fill_pinyin(&db.pool).await.unwrap();
+        let q = SearchQuery {
+            path_prefix: Some(root),
+            ..Default::default()
+        };
+        let found: Vec<File> = db.search(&q).try_collect().await.unwrap();
+        assert_eq!(found[0].pinyin, "yuyan yy");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remove_stale() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let gone = File::new(
+            format!("{root}/gone.mp4"),
+            "gone.mp4".to_string(),
+            false,
+            None,
+        );
+        let other = File::new(
+            format!("{root}-other/gone.mp4"),
+            "gone.mp4".to_string(),
+            false,
+            None,
+        );
+        let unread = File::new(
+            format!("{root}/locked/unread.mp4"),
+            "unread.mp4".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &unread).await.unwrap();
+        let since = Utc::now();
+        let keep = File::new(
+            format!("{root}/keep.mp4"),
+            "keep.mp4".to_string(),
+            false,
+            None,
+        );
+
+        // the same path seen from another host is that host's to remove.
+        let elsewhere = File::new(
+            format!("{root}/elsewhere.mp4"),
+            "elsewhere.mp4".to_string(),
+            false,
+            Some("other:host".to_string()),
+        );
+
+        db_save(&mut db, &keep).await.unwrap();
+        db_save(&mut db, &gone).await.unwrap();
+        db_save(&mut db, &other).await.unwrap();
+        db_save(&mut db, &elsewhere).await.unwrap();
+
+        let filter = FileFilter::new(&[Category::Video], &[]);
+        // a directory that could not be read keeps what was in it.
+        let locked = [format!("{root}/locked")];
+        let removed = db
+            .remove_stale(&root, &[], since, &locked, &filter)
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![gone.full_path.clone()]);
+        let removed = db
+            .remove_stale(&root, &[], since, &[], &filter)
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![unread.full_path.clone()]);
+
+        let removed = db
+            .remove_stale(&format!("{root}-other"), &[], Utc::now(), &[], &filter)
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![other.full_path.clone()]);
+        assert_eq!(
+            paths_under(&db, &root).await,
+            vec![elsewhere.full_path.clone(), keep.full_path.clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_report() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let mut report = ScanReport::new(&format!("/tmp/find_videos/{}", uuid_v4()));
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        report.errors.push(crate::report::ScanError::io(
+            &Path::new(&report.root).join("private"),
+            &denied,
+        ));
+        db.save_report(&report).await.unwrap();
+
+        let saved: Vec<(String, String, String)> =
+            sqlx::query_as("select path, kind, message from scan_errors where scan_id = ?1")
+                .bind(report.id.as_str())
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            saved,
+            vec![(
+                format!("{}/private", report.root),
+                "permission".to_string(),
+                denied.to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_events() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        // every scan makes its files anew, with ids of their own.
+        let file = || File::new(format!("{root}/a.mkv"), "a.mkv".to_string(), false, None);
+        async fn events(db: &Sqlite, path: &str) -> Vec<String> {
+            sqlx::query_scalar(
+                "select event_type from events where full_path = ?1 order by timestamp",
+            )
+            .bind(path)
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+        }
+
+        let f = file();
+        let saved = db.save_bulk(std::slice::from_ref(&f)).await.unwrap();
+        assert_eq!(saved, Saved { new: 1, updated: 0 });
+        // seen again as it was, which is no event.
+        db.save_bulk(&[file()]).await.unwrap();
+        assert_eq!(events(&db, &f.full_path).await, vec!["create"]);
+
+        let mut changed = file();
+        changed.size += 1;
+        let saved = db.save_bulk(&[changed]).await.unwrap();
+        assert_eq!(saved, Saved { new: 0, updated: 1 });
+        assert_eq!(events(&db, &f.full_path).await, vec!["create", "modify"]);
+    }
+
+    #[tokio::test]
+    async fn test_rename_remove() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let file = |path: &str, dir: bool| {
+            let name = path.rsplit('/').next().unwrap().to_string();
+            File::new(format!("{root}/{path}"), name, dir, None)
+        };
+        let show = file("show", true);
+        let mut episode = file("show/e01.mkv", false);
+        episode.hash = Some("abc".to_string());
+        let replaced = file("moved", false);
+        for f in [&show, &episode, &replaced] {
+            db_save(&mut db, f).await.unwrap();
+        }
+
+        let moved = file("moved", true);
+        assert!(db.rename(&show.full_path, &moved).await.unwrap());
+        assert!(!db.rename(&show.full_path, &moved).await.unwrap());
+        let found: Vec<_> = db
+            .search(&SearchQuery {
+                path_prefix: Some(root.clone()),
+                ..Default::default()
+            })
+            .try_collect()
+            .await
+            .unwrap();
+        let mut found: Vec<_> = found
+            .into_iter()
+            .map(|f| (f.id, f.full_path, f.hash))
+            .collect();
+        found.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            found,
+            vec![
+                (show.id.clone(), moved.full_path.clone(), None),
+                (
+                    episode.id.clone(),
+                    format!("{root}/moved/e01.mkv"),
+                    Some("abc".to_string())
+                ),
+            ]
+        );
+
+        let mut changed = file("moved/e01.mkv", false);
+        changed.size = 42;
+        db.save_modified(&changed).await.unwrap();
+        // a second change of the same file is recorded as well.
+        changed.size = 43;
+        db.save_modified(&changed).await.unwrap();
+        let events: Vec<(String, Option<String>)> = sqlx::query_as(
+            "select event_type, from_path from events
+                 where full_path like ?1 and event_type in ('modify', 'rename') order by event_type",
+        )
+        .bind(format!("{root}/%"))
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ("modify".to_string(), None),
+                ("modify".to_string(), None),
+                ("rename".to_string(), Some(show.full_path.clone())),
+            ]
+        );
+
+        let removed = db.remove(&format!("{root}/moved/")).await.unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(paths_under(&db, &root).await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_save_metadata() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let meta = fs::metadata("Cargo.toml").unwrap();
+        let file_name = format!("{}.toml", uuid_v4());
+        let full_path = format!("/tmp/find_videos/{file_name}");
+        let mut f = File::new(full_path, file_name.clone(), false, None).with_metadata(&meta);
+
+        db_save(&mut db, &f).await.unwrap();
+        // a rescan of the same path refreshes the metadata in place.
+        f.id = uuid_v4();
+        f.size += 1;
+        db_save(&mut db, &f).await.unwrap();
+
+        let q = SearchQuery {
+            name: file_name,
+            ..Default::default()
+        };
+        let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, meta.len() as i64 + 1);
+        assert_eq!(files[0].inode, f.inode);
+        assert_eq!(
+            files[0].modified.map(|t| t.timestamp_micros()),
+            f.modified.map(|t| t.timestamp_micros())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_escape() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let dir = format!("/tmp/find_videos/{}", uuid_v4());
+        let quoted = File::new(
+            format!("{dir}/Tom's_100%.mp4"),
+            "Tom's_100%.mp4".to_string(),
+            false,
+            None,
+        );
+        let plain = File::new(
+            format!("{dir}/Toms-1000.mp4"),
+            "Toms-1000.mp4".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &quoted).await.unwrap();
+        db_save(&mut db, &plain).await.unwrap();
+
+        for name in ["Tom's", "s_100%"] {
+            let q = SearchQuery {
+                name: name.to_string(),
+                ..Default::default()
+            };
+            let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+            assert!(files.iter().any(|f| f.full_path == quoted.full_path));
+            assert!(files.iter().all(|f| f.full_path != plain.full_path));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_full_text() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        let dir = format!("/tmp/find_videos/{token}");
+        let show = File::new(
+            format!("{dir}/The.Show.S02E05.1080p.mkv"),
+            "The.Show.S02E05.1080p.mkv".to_string(),
+            false,
+            None,
+        );
+        let other = File::new(
+            format!("{dir}/Other.Show.S01E01.720p.mkv"),
+            "Other.Show.S01E01.720p.mkv".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &show).await.unwrap();
+        db_save(&mut db, &other).await.unwrap();
+
+        let search = |name: String| {
+            let db = &db;
+            async move {
+                let q = SearchQuery {
+                    name,
+                    mode: SearchMode::FullText,
+                    ..Default::default()
+                };
+                db.search(&q)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|f| f.full_path)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let found = search(format!("{token} AND s02e05")).await;
+        assert_eq!(found, vec![show.full_path.clone()]);
+
+        let found = search(format!("{token} AND sho*")).await;
+        assert_eq!(found.len(), 2);
+
+        let found = search(format!("{token} AND \"other show\"")).await;
+        assert_eq!(found, vec![other.full_path.clone()]);
+
+        let found = search(format!("{token} NOT 1080p")).await;
+        assert_eq!(found, vec![other.full_path.clone()]);
+
+        let found = search(format!("{token} AND (s02e05 OR s01e01)")).await;
+        assert_eq!(found.len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_terms() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        let name = format!("The.Show.S02E05.1080p.WEB-DL.{token}.mkv");
+        let show = File::new(format!("/tmp/find_videos/{name}"), name, false, None);
+        db_save(&mut db, &show).await.unwrap();
+
+        for name in ["show s02e05", "S02E05 show", "web-dl the", "1080 how"] {
+            let q = SearchQuery {
+                name: format!("{name} {token}"),
+                ..Default::default()
+            };
+            let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+            assert_eq!(files.len(), 1, "{name}");
+        }
+
+        let q = SearchQuery {
+            name: format!("show s01 {token}"),
+            ..Default::default()
+        };
+        assert!(db
+            .search(&q)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_pinyin() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        let name = format!("go語言基礎.{token}.mp4");
+        let f = File::new(format!("/tmp/find_videos/{name}"), name, false, None);
+        db_save(&mut db, &f).await.unwrap();
+
+        for name in [
+            "yyjc",
+            "yuyan",
+            "goyuyanjichu",
+            "语言",
+            "語言基礎",
+            "基础 go",
+        ] {
+            let q = SearchQuery {
+                name: format!("{name} {token}"),
+                ..Default::default()
+            };
+            let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+            assert_eq!(files.len(), 1, "{name}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_nfd() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        // what a macOS volume hands out for `Café`.
+        let name = format!("Cafe\u{301}.{token}.mp4");
+        let f = File::new(format!("/Volumes/Media/{name}"), name, false, None);
+        assert_eq!(f.file_name, format!("Caf\u{e9}.{token}.mp4"));
+        db_save(&mut db, &f).await.unwrap();
+
+        for name in ["café", "CAFÉ", "Cafe\u{301}"] {
+            let q = SearchQuery {
+                name: format!("{name} {token}"),
+                ..Default::default()
+            };
+            let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+            assert_eq!(files.len(), 1, "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fill_search_paths() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let f = File::new(
+            format!("{root}/Cafe\u{301}.mkv"),
+            "Cafe\u{301}.mkv".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &f).await.unwrap();
+        // saved before there was a search path.
+        sqlx::query("update file set search_path = '' where id = ?1")
+            .bind(f.id.as_str())
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        sqlx::query("pragma user_version = 1")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        Sqlite::fill_search_paths(&db.pool).await.unwrap();
+        let path: String = sqlx::query_scalar("select search_path from file where id = ?1")
+            .bind(f.id.as_str())
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(path, format!("{root}/Café.mkv"));
+    }
+
+    #[tokio::test]
+    async fn test_search_nfd_path() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        // as macOS hands them out, the file name alone is normalized.
+        let dir = format!("{root}/Cafe\u{301}");
+        let f = File::new(format!("{dir}/a.mkv"), "a.mkv".to_string(), false, None);
+        db_save(&mut db, &f).await.unwrap();
+        assert_eq!(f.full_path, format!("{root}/Cafe\u{301}/a.mkv"));
+
+        async fn search(db: &Sqlite, name: &str, mode: SearchMode, prefix: &str) -> Vec<String> {
+            let q = SearchQuery {
+                name: name.to_string(),
+                mode,
+                full_path: true,
+                path_prefix: Some(prefix.to_string()),
+                ..Default::default()
+            };
+            db.search(&q)
+                .map_ok(|f| f.full_path)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+        }
+        let nfc = format!("{root}/Café");
+        let found = vec![f.full_path.clone()];
+        assert_eq!(
+            search(&db, "CAFÉ", SearchMode::Substring, &root).await,
+            found
+        );
+        assert_eq!(
+            search(&db, "*/Café/*", SearchMode::Glob, &root).await,
+            found
+        );
+        let re = Regex::new("Café/a").unwrap();
+        assert_eq!(search(&db, "", SearchMode::Regex(re), &root).await, found);
+        assert_eq!(search(&db, "", SearchMode::Substring, &nfc).await, found);
+
+        // a rename moves the search path along.
+        let moved = File::new(format!("{dir}/b.mkv"), "b.mkv".to_string(), false, None);
+        assert!(db.rename(&f.full_path, &moved).await.unwrap());
+        let found = vec![moved.full_path.clone()];
+        assert_eq!(
+            search(&db, "*/Café/b.mkv", SearchMode::Glob, &nfc).await,
+            found
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_glob_regex() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        let dir = format!("/tmp/find_videos/{token}");
+        let e03 = File::new(
+            format!("{dir}/Show.S01E03.1080p.mkv"),
+            "Show.S01E03.1080p.mkv".to_string(),
+            false,
+            None,
+        );
+        let e12 = File::new(
+            format!("{dir}/Show.S01E12.2160p.mkv"),
+            "Show.S01E12.2160p.mkv".to_string(),
+            false,
+            None,
+        );
+        let other = File::new(
+            format!("{dir}/Show.S01E04.720p.mp4"),
+            "Show.S01E04.720p.mp4".to_string(),
+            false,
+            None,
+        );
+        for f in [&e03, &e12, &other] {
+            db_save(&mut db, f).await.unwrap();
+        }
+
+        let search = |name: &str, mode: SearchMode, full_path: bool| {
+            let db = &db;
+            let q = SearchQuery {
+                name: name.to_string(),
+                mode,
+                full_path,
+                ..Default::default()
+            };
+            async move {
+                let mut found = db
+                    .search(&q)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|f| f.full_path)
+                    .collect::<Vec<_>>();
+                found.sort();
+                found
+            }
+        };
+
+        let found = search(&format!("{dir}/*S01E0?*.mkv"), SearchMode::Glob, true).await;
+        assert_eq!(found, vec![e03.full_path.clone()]);
+
+        let re = Regex::new(&format!("^{dir}/.*(?i)(1080P|2160P)")).unwrap();
+        let found = search("", SearchMode::Regex(re), true).await;
+        assert_eq!(found, vec![e03.full_path.clone(), e12.full_path.clone()]);
+
+        // only the name is matched without `full_path`.
+        let re = Regex::new(&format!("^{dir}")).unwrap();
+        assert!(search("", SearchMode::Regex(re), false).await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_filters() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let token = uuid_v4();
+        let dir = format!("/tmp/find_videos/{token}");
+        let file = |name: &str, size: i64, year: i32, hostname: &str| {
+            let mut f = File::new(
+                format!("{dir}/{name}"),
+                name.to_string(),
+                false,
+                Some(hostname.to_string()),
+            );
+            f.size = size;
+            f.modified = Utc.with_ymd_and_hms(year, 6, 1, 0, 0, 0).single();
+            f
+        };
+        let big = file("big.MKV", 3 << 30, 2022, "nas:liwei");
+        let small = file("small.mkv", 2 << 20, 2022, "nas:liwei");
+        let old = file("old.mp4", 3 << 30, 2019, "nas:liwei");
+        let song = file("song.flac", 3 << 30, 2022, "mac:liwei");
+        let mut sub = File::new(
+            format!("{dir}/sub"),
+            "sub".to_string(),
+            true,
+            Some("nas:liwei".to_string()),
+        );
+        sub.modified = big.modified;
+        let nested = file("sub/nested.mkv", 3 << 30, 2022, "nas:liwei");
+        for f in [&big, &small, &old, &song, &sub, &nested] {
+            db_save(&mut db, f).await.unwrap();
+        }
+
+        let search = |q: SearchQuery| {
+            let db = &db;
+            async move {
+                let mut found = db
+                    .search(&q)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|f| f.file_name)
+                    .collect::<Vec<_>>();
+                found.sort();
+                found
+            }
+        };
+        let base = SearchQuery {
+            path_prefix: Some(format!("{dir}/")),
+            ..Default::default()
+        };
+
+        // videos over 2 GB modified in 2022 on the nas.
+        let q = SearchQuery {
+            extensions: Category::Video
+                .extensions()
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            min_size: Some(2 << 30),
+            modified_after: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).single(),
+            modified_before: Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).single(),
+            hostname: Some("nas".to_string()),
+            kind: Some(Kind::File),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["big.MKV", "sub/nested.mkv"]);
+
+        let q = SearchQuery {
+            kind: Some(Kind::Dir),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["sub"]);
+
+        let q = SearchQuery {
+            path_prefix: Some(format!("{dir}/sub")),
+            max_size: Some(3 << 30),
+            hostname: Some("nas:liwei".to_string()),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["sub", "sub/nested.mkv"]);
+
+        let q = SearchQuery {
+            name: "s".to_string(),
+            extensions: vec!["flac".to_string(), "mp4".to_string()],
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["song.flac"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_sort_limit() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let dir = format!("/tmp/find_videos/{}", uuid_v4());
+        for (name, size) in [("b.mkv", 3), ("a.mkv", 2), ("d.mp4", 1), ("c.mkv", 4)] {
+            let mut f = File::new(format!("{dir}/{name}"), name.to_string(), false, None);
+            f.size = size;
+            db_save(&mut db, &f).await.unwrap();
+        }
+
+        let search = |q: SearchQuery| {
+            let db = &db;
+            async move {
+                db.search(&q)
+                    .map_ok(|f| f.file_name)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            }
+        };
+        let base = SearchQuery {
+            path_prefix: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let q = SearchQuery {
+            sort: Some(Sort::Name),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["a.mkv", "b.mkv", "c.mkv", "d.mp4"]);
+
+        let q = SearchQuery {
+            sort: Some(Sort::Size),
+            reverse: true,
+            limit: Some(2),
+            offset: Some(1),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["b.mkv", "a.mkv"]);
+
+        // limit and offset count matches of the regex, not rows.
+        let q = SearchQuery {
+            mode: SearchMode::Regex(Regex::new("mkv$").unwrap()),
+            sort: Some(Sort::Path),
+            limit: Some(2),
+            offset: Some(1),
+            ..base.clone()
+        };
+        assert_eq!(search(q).await, vec!["b.mkv", "c.mkv"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_save_volume() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let mut v = Volume::detect(Path::new("Cargo.toml")).unwrap();
+        v.id = uuid_v4();
+        db.save_volume(&v).await.unwrap();
+        v.mount_point = "/Volumes/Media 1".to_string();
+        db.save_volume(&v).await.unwrap();
+
+        let volumes = db.volumes().await.unwrap();
+        let saved = volumes.iter().find(|s| s.id == v.id).unwrap();
+        assert_eq!(saved.mount_point, "/Volumes/Media 1");
+
+        let name = format!("{}.mkv", uuid_v4());
+        let mut f = File::new(
+            format!("/Volumes/Media 1/{name}"),
+            name.clone(),
+            false,
+            None,
+        );
+        f.volume_id = Some(v.id.clone());
+        db_save(&mut db, &f).await.unwrap();
+        let q = SearchQuery {
+            name,
+            ..Default::default()
+        };
+        let files = db.search(&q).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(files[0].volume_id, Some(v.id.clone()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remount() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let mut v = Volume::detect(Path::new("Cargo.toml")).unwrap();
+        v.id = uuid_v4();
+        v.mount_point = format!("/Volumes/{}", uuid_v4());
+        db.save_volume(&v).await.unwrap();
+
+        let file = |v: &Volume, name: &str| {
+            File::new(
+                format!("{}/films/{name}", v.mount_point),
+                name.to_string(),
+                false,
+                None,
+            )
+            .with_volume(v)
+        };
+        let kept = file(&v, "kept.mkv");
+        let gone = file(&v, "gone.mkv");
+        db_save(&mut db, &kept).await.unwrap();
+        db_save(&mut db, &gone).await.unwrap();
+        assert_eq!(kept.rel_path, "films/kept.mkv");
+
+        // the same disk shows up at another mount point.
+        let old = v.mount_point.clone();
+        v.mount_point = format!("{old} 1");
+        db.save_volume(&v).await.unwrap();
+        let since = Utc::now();
+        let kept = file(&v, "kept.mkv");
+        db_save(&mut db, &kept).await.unwrap();
+
+        assert!(paths_under(&db, &old).await.is_empty());
+        assert_eq!(
+            paths_under(&db, &v.mount_point).await,
+            vec![
+                format!("{}/films/gone.mkv", v.mount_point),
+                kept.full_path.clone()
+            ]
+        );
+
+        let removed = db
+            .remove_stale(
+                &format!("{}/films", v.mount_point),
+                &[v.clone()],
+                since,
+                &[],
+                &FileFilter::new(&[Category::Video], &[]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![format!("{}/films/gone.mkv", v.mount_point)]);
+        assert_eq!(
+            paths_under(&db, &v.mount_point).await,
+            vec![kept.full_path.clone()]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dupes() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let since = Utc::now();
+        // sizes no other test uses, so only these files collide.
+        let size = (rand_size() << 20) + 7;
+        let file = |name: &str, size: i64| {
+            let mut f = File::new(format!("{root}/{name}"), name.to_string(), false, None);
+            f.size = size;
+            f
+        };
+        let (a, b, c, alone) = (
+            file("a.mkv", size),
+            file("b.mkv", size),
+            file("c.mkv", size),
+            file("alone.mkv", size + 1),
+        );
+        for f in [&a, &b, &c, &alone] {
+            db_save(&mut db, f).await.unwrap();
+        }
+
+        let names = |files: Vec<File>| files.into_iter().map(|f| f.file_name).collect::<Vec<_>>();
+        let candidates = db.partial_hash_candidates(&root, since).await.unwrap();
+        assert_eq!(names(candidates.clone()), vec!["a.mkv", "b.mkv", "c.mkv"]);
+
+        // a and b have the same ends, c differs already there.
+        for mut f in candidates {
+            f.partial_hash = Some(if f.file_name == "c.mkv" { "c" } else { "ab" }.to_string());
+            db.save_hash(&f).await.unwrap();
+        }
+        assert!(db
+            .partial_hash_candidates(&root, since)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let candidates = db.full_hash_candidates(&root, since).await.unwrap();
+        assert_eq!(names(candidates.clone()), vec!["a.mkv", "b.mkv"]);
+        let hash = uuid_v4();
+        for mut f in candidates {
+            f.hash = Some(hash.clone());
+            db.save_hash(&f).await.unwrap();
+        }
+
+        // a rescan of an unchanged file keeps its hashes.
+        db_save(&mut db, &a).await.unwrap();
+        let groups = db.dupes(size).await.unwrap();
+        let group = groups
+            .into_iter()
+            .find(|g| g[0].hash.as_deref() == Some(hash.as_str()))
+            .unwrap();
+        assert_eq!(names(group), vec!["a.mkv", "b.mkv"]);
+
+        // a changed one loses them.
+        let mut changed = a.clone();
+        changed.size += 1;
+        db_save(&mut db, &changed).await.unwrap();
+        assert!(db
+            .dupes(size)
+            .await
+            .unwrap()
+            .iter()
+            .all(|g| g[0].hash.as_deref() != Some(hash.as_str())));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_hash_candidates_two_roots() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let base = format!("/tmp/find_videos/{}", uuid_v4());
+        let size = (rand_size() << 20) + 11;
+        let file = |path: String| {
+            let name = path.rsplit('/').next().unwrap().to_string();
+            let mut f = File::new(path, name, false, None);
+            f.size = size;
+            f
+        };
+        // the old copy was alone when its root was scanned, so it has no hash.
+        let old = file(format!("{base}/old/a.mkv"));
+        db_save(&mut db, &old).await.unwrap();
+        let since = Utc::now();
+        let new = file(format!("{base}/new/a.mkv"));
+        db_save(&mut db, &new).await.unwrap();
+
+        let root = format!("{base}/new");
+        let paths = |files: Vec<File>| files.into_iter().map(|f| f.full_path).collect::<Vec<_>>();
+        let candidates = db.partial_hash_candidates(&root, since).await.unwrap();
+        assert_eq!(
+            paths(candidates.clone()),
+            vec![new.full_path.clone(), old.full_path.clone()]
+        );
+        for mut f in candidates {
+            f.partial_hash = Some("same".to_string());
+            db.save_hash(&f).await.unwrap();
+        }
+
+        let candidates = db.full_hash_candidates(&root, since).await.unwrap();
+        assert_eq!(
+            paths(candidates),
+            vec![new.full_path.clone(), old.full_path.clone()]
+        );
+        // the old root alone, seen before `since`, has nothing to hash.
+        assert!(db
+            .full_hash_candidates(&format!("{base}/old"), since)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_media_info() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let since = Utc::now();
+        let hd = File::new(format!("{root}/hd.mp4"), "hd.mp4".to_string(), false, None);
+        let sd = File::new(format!("{root}/sd.mp4"), "sd.mp4".to_string(), false, None);
+        // no reader takes it, so it never gets media info and is no candidate either.
+        let avi = File::new(
+            format!("{root}/old.AVI"),
+            "old.AVI".to_string(),
+            false,
+            None,
+        );
+        db_save(&mut db, &hd).await.unwrap();
+        db_save(&mut db, &sd).await.unwrap();
+        db_save(&mut db, &avi).await.unwrap();
+        assert_eq!(
+            db.media_info_candidates(&root, since).await.unwrap().len(),
+            2
+        );
+
+        for (f, height) in [(&hd, 1080), (&sd, 480)] {
+            let info = MediaInfo {
+                file_id: f.id.clone(),
+                duration_ms: Some(90 * 60_000),
+                width: Some(height * 16 / 9),
+                height: Some(height),
+                video_codec: Some("h264".to_string()),
+                audio_codec: Some("aac".to_string()),
+                created: None,
+                title: None,
+                tracks: vec![
+                    Track {
+                        number: 1,
+                        kind: TrackKind::Audio,
+                        codec: Some("aac".to_string()),
+                        language: Some("jpn".to_string()),
+                        name: None,
+                    },
+                    Track {
+                        number: 2,
+                        kind: TrackKind::Subtitle,
+                        codec: Some("ass".to_string()),
+                        language: Some(if height > 720 { "zho" } else { "eng" }.to_string()),
+                        name: None,
+                    },
+                ],
+                tags: None,
+            };
+            db.save_media_info(&info).await.unwrap();
+        }
+        assert!(db
+            .media_info_candidates(&root, since)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let q = SearchQuery {
+            path_prefix: Some(root.clone()),
+            min_height: Some(1080),
+            video_codec: Some("h264".to_string()),
+            audio_lang: Some("jpn".to_string()),
+            subtitle_lang: Some("chi".to_string()),
+            min_duration_ms: Some(60 * 60_000),
+            ..Default::default()
+        };
+        let found = db.search(&q).map_ok(|f| f.file_name);
+        assert_eq!(found.try_collect::<Vec<_>>().await.unwrap(), vec!["hd.mp4"]);
+
+        let by_lang = SearchQuery {
+            path_prefix: Some(root.clone()),
+            audio_lang: Some("JPN".to_string()),
+            ..Default::default()
+        };
+        let found = db.search(&by_lang).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(found.len(), 2);
+
+        // a changed file is read again.
+        let mut changed = hd.clone();
+        changed.size += 1;
+        db_save(&mut db, &changed).await.unwrap();
+        let candidates = db.media_info_candidates(&root, since).await.unwrap();
+        assert_eq!(candidates[0].id, hd.id);
+        assert!(db
+            .search(&q)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_audio_tags() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = format!("/tmp/find_videos/{}", uuid_v4());
+        let songs = [
+            ("qilixiang.mp3", "周杰伦", "七里香", "Pop", 2004),
+            ("100%.flac", "Various Artists", "100% Hits", "Dance", 2019),
+        ];
+        for (name, artist, album, genre, year) in songs {
+            let f = File::new(format!("{root}/{name}"), name.to_string(), false, None);
+            db_save(&mut db, &f).await.unwrap();
+            let info = MediaInfo {
+                file_id: f.id.clone(),
+                title: None,
+                tags: Some(Tags {
+                    title: Some(format!("{album} title")),
+                    artist: Some(artist.to_string()),
+                    album: Some(album.to_string()),
+                    album_artist: Some(format!("{artist} band")),
+                    genre: Some(genre.to_string()),
+                    track: Some(1),
+                    year: Some(year),
+                }),
+                ..Default::default()
+            };
+            db.save_media_info(&info).await.unwrap();
+        }
+
+        let search = |q: SearchQuery| {
+            let db = &db;
+            let q = SearchQuery {
+                path_prefix: Some(root.clone()),
+                sort: Some(Sort::Name),
+                ..q
+            };
+            async move {
+                let found = db.search(&q).map_ok(|f| f.file_name);
+                found.try_collect::<Vec<_>>().await.unwrap()
+            }
+        };
+        let q = SearchQuery {
+            artist: Some("杰伦".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(q).await, vec!["qilixiang.mp3"]);
+        let q = SearchQuery {
+            artist: Some("various artists BAND".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(q).await, vec!["100%.flac"]);
+        let q = SearchQuery {
+            album: Some("100%".to_string()),
+            year: Some(2019),
+            ..Default::default()
+        };
+        assert_eq!(search(q).await, vec!["100%.flac"]);
+        let q = SearchQuery {
+            title: Some("title".to_string()),
+            genre: Some("pop".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(q).await, vec!["qilixiang.mp3"]);
+        let q = SearchQuery {
+            year: Some(1999),
+            ..Default::default()
+        };
+        assert!(search(q).await.is_empty());
+    }
+
+    fn rand_size() -> i64 {
+        i64::from_str_radix(&uuid_v4()[..8], 16).unwrap()
     }
 }
@@ -0,0 +1,60 @@
+use crate::database::Database;
+use crate::output::{Format, Printer};
+use crate::util::{format_size, parse_size};
+use clap::Subcommand;
+use eyre::Result;
+use std::io;
+
+#[derive(Debug, Subcommand)]
+pub enum DupesCommand {
+    /// files with the same content on any disk or host, hashed by `scan --hash`.
+    Dupes {
+        /// leave out files smaller than this, `100M`.
+        #[arg(long, value_parser = parse_size)]
+        min_size: Option<i64>,
+        /// also show size, modification time and inode.
+        #[arg(long, short = 'l')]
+        long: bool,
+    },
+}
+
+impl DupesCommand {
+    pub async fn run(self, db: &mut impl Database) -> Result<()> {
+        match self {
+            Self::Dupes { min_size, long } => {
+                let groups = db.dupes(min_size.unwrap_or(1)).await?;
+                let volumes = db.volumes().await?;
+                let mut printer = Printer::new(io::stdout(), Format::Plain, vec![])
+                    .plain(true, long)
+                    .volumes(volumes);
+
+                let mut wasted = 0;
+                for group in &groups {
+                    // every copy but one is wasted.
+                    let size = group[0].size;
+                    let extra = size * (group.len() as i64 - 1);
+                    wasted += extra;
+                    println!(
+                        "{} copies of {}, {} wasted:",
+                        group.len(),
+                        format_size(size),
+                        format_size(extra)
+                    );
+                    for f in group {
+                        printer.print(f)?;
+                    }
+                    println!();
+                }
+                printer.finish()?;
+
+                println!(
+                    "{} files with copies, {} wasted.",
+                    groups.len(),
+                    format_size(wasted)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::file::File;
-use crate::util::uuid_v4;
+use crate::util::{hostname, uuid_v4};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +7,10 @@ use serde::{Deserialize, Serialize};
 pub enum EventType {
     Create,
     Delete,
+    /// contents or times of a file changed, seen by `watch`.
+    Modify,
+    /// moved within a volume, `full_path` is where it went.
+    Rename,
 }
 
 #[derive(Debug)]
@@ -16,6 +20,8 @@ pub struct Event {
     pub hostname: String,
     pub event_type: EventType,
     pub full_path: String,
+    /// where a renamed file was before.
+    pub from_path: Option<String>,
 }
 
 impl Event {
@@ -26,19 +32,33 @@ impl Event {
             hostname: f.hostname.clone(),
             event_type: EventType::Create,
             full_path: f.full_path.clone(),
+            from_path: None,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn new_delete(full_path: &str) -> Event {
-        let hostname = format!("{}:{}", whoami::hostname(), whoami::username());
+    pub fn new_modify(f: &File) -> Event {
+        Event {
+            event_type: EventType::Modify,
+            ..Event::new_create(f)
+        }
+    }
 
+    pub fn new_rename(from_path: &str, f: &File) -> Event {
+        Event {
+            event_type: EventType::Rename,
+            from_path: Some(from_path.to_string()),
+            ..Event::new_create(f)
+        }
+    }
+
+    pub fn new_delete(full_path: &str) -> Event {
         Event {
             id: uuid_v4(),
             timestamp: chrono::Utc::now(),
-            hostname,
+            hostname: hostname(),
             event_type: EventType::Delete,
             full_path: full_path.to_string(),
+            from_path: None,
         }
     }
 }
@@ -0,0 +1,108 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// per directory exclude rules, in the same syntax as `.gitignore`.
+pub const IGNORE_FILE: &str = ".findvignore";
+
+/// what `scan` leaves out: the patterns from config.toml and `--exclude`, plus the
+/// `.findvignore` files of the directories walked so far.
+#[derive(Clone)]
+pub struct Excludes {
+    root: PathBuf,
+    /// rooted at `/`, so `/Volumes/Macintosh*` is anchored and `*.part` matches anywhere.
+    global: Gitignore,
+    /// `.findvignore` of every directory asked about, `None` where there is none.
+    dirs: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl Excludes {
+    /// `root` is where the walk starts, ignore files above it are not read.
+    pub fn new(root: impl AsRef<Path>, patterns: &[String]) -> Result<Self, ignore::Error> {
+        let mut builder = GitignoreBuilder::new("/");
+        for p in patterns {
+            builder.add_line(None, p)?;
+        }
+
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+            global: builder.build()?,
+            dirs: HashMap::new(),
+        })
+    }
+
+    fn load(dir: &Path) -> Option<Gitignore> {
+        let path = dir.join(IGNORE_FILE);
+        if !path.is_file() {
+            return None;
+        }
+
+        let (gitignore, err) = Gitignore::new(&path);
+        if let Some(e) = err {
+            warn!("{}: {}", path.display(), e);
+        }
+        Some(gitignore)
+    }
+
+    /// whether `path` is left out. the nearest `.findvignore` with a matching rule decides,
+    /// so `!pattern` in a subdirectory can take back what a parent excluded.
+    pub fn is_excluded(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let gitignore = self
+                .dirs
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| Self::load(dir));
+            match gitignore.as_ref().map(|g| g.matched(path, is_dir)) {
+                Some(Match::Ignore(_)) => return true,
+                Some(Match::Whitelist(_)) => return false,
+                _ => {}
+            }
+        }
+
+        self.global.matched(path, is_dir).is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::log_init;
+    use crate::util::uuid_v4;
+    use std::fs;
+
+    #[test]
+    fn test_excludes() {
+        log_init();
+        let root = std::env::temp_dir().join(uuid_v4());
+        fs::create_dir_all(root.join("films/extras")).unwrap();
+        fs::write(root.join(IGNORE_FILE), "extras/\n*.nfo\n").unwrap();
+        fs::write(root.join("films").join(IGNORE_FILE), "!keep.nfo\n").unwrap();
+
+        let patterns = vec!["*.part".to_string(), "/Volumes/Macintosh*".to_string()];
+        let mut excludes = Excludes::new(&root, &patterns).unwrap();
+        let cases = [
+            ("films/a.mkv", false, false),
+            ("films/a.mkv.part", false, true),
+            ("films/extras", true, true),
+            ("films/extras", false, false),
+            ("films/a.nfo", false, true),
+            ("films/keep.nfo", false, false),
+        ];
+        for (path, is_dir, excluded) in cases {
+            assert_eq!(
+                excludes.is_excluded(&root.join(path), is_dir),
+                excluded,
+                "{path}"
+            );
+        }
+        assert!(excludes.is_excluded(Path::new("/Volumes/Macintosh HD"), true));
+        assert!(!excludes.is_excluded(Path::new("/Volumes/Media"), true));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
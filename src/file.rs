@@ -1,6 +1,10 @@
-use crate::util::uuid_v4;
-use chrono::Utc;
+use crate::chinese::{fold, pinyin_key};
+use crate::search::{normalize, tokenize};
+use crate::util::{self, uuid_v4};
+use crate::volume::Volume;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, sqlx::FromRow)]
 pub struct File {
@@ -10,19 +14,83 @@ pub struct File {
     pub hostname: String,
     pub dir: bool,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub size: i64,
+    pub modified: Option<chrono::DateTime<chrono::Utc>>,
+    pub created: Option<chrono::DateTime<chrono::Utc>>,
+    pub device: i64,
+    pub inode: i64,
+    /// `Volume::id` of the disk the file is on.
+    pub volume_id: Option<String>,
+    /// path inside the volume, what identifies the file together with `volume_id` wherever the
+    /// disk is mounted. same as `full_path` when the volume is unknown.
+    pub rel_path: String,
+    /// when a scan last found the file on disk.
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    /// blake3 of the whole file, only for files that might have a copy, see `hash::full_hash`.
+    pub hash: Option<String>,
+    /// see `hash::partial_hash`.
+    #[serde(skip)]
+    pub partial_hash: Option<String>,
+    /// words of `file_name` that `find` matches against, chinese folded to simplified.
+    #[serde(skip)]
+    pub tokens: String,
+    /// pinyin of the chinese words in `tokens`, see `chinese::pinyin_key`.
+    #[serde(skip)]
+    pub pinyin: String,
 }
 
 impl File {
     pub fn new(full_path: String, file_name: String, dir: bool, hostname: Option<String>) -> Self {
-        let hostname =
-            hostname.unwrap_or_else(|| format!("{}:{}", whoami::hostname(), whoami::username()));
+        let hostname = hostname.unwrap_or_else(util::hostname);
+        let file_name = normalize(&file_name);
+        let words = tokenize(&fold(&file_name));
+        let pinyin = pinyin_key(&words);
+        let tokens = words.join(" ");
+        let now = Utc::now();
         Self {
             id: uuid_v4(),
+            rel_path: full_path.clone(),
             full_path,
             file_name,
-            timestamp: Utc::now(),
+            timestamp: now,
+            last_seen: now,
             hostname,
             dir,
+            size: 0,
+            modified: None,
+            created: None,
+            device: 0,
+            inode: 0,
+            volume_id: None,
+            hash: None,
+            partial_hash: None,
+            tokens,
+            pinyin,
         }
     }
+
+    /// fill size, times and inode from what the walk saw on disk.
+    pub fn with_metadata(mut self, meta: &Metadata) -> Self {
+        self.size = meta.len() as i64;
+        self.modified = meta.modified().ok().map(DateTime::<Utc>::from);
+        self.created = meta.created().ok().map(DateTime::<Utc>::from);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            self.device = meta.dev() as i64;
+            self.inode = meta.ino() as i64;
+        }
+
+        self
+    }
+
+    /// place the file on volume `v`, left without one when it is not under the mount point.
+    pub fn with_volume(mut self, v: &Volume) -> Self {
+        if let Some(rel_path) = v.relative(&self.full_path) {
+            self.volume_id = Some(v.id.clone());
+            self.rel_path = rel_path;
+        }
+        self
+    }
 }
@@ -1,45 +1,229 @@
 use crate::database::Database;
+use crate::media::Category;
+use crate::output::{Column, Format, Printer};
+use crate::search::{normalize, Kind, SearchMode, SearchQuery, Sort};
 use crate::settings::Settings;
-use clap::Subcommand;
+use crate::util::{parse_duration, parse_size, parse_time};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
 use eyre::Result;
+use futures::TryStreamExt;
+use regex::Regex;
+use std::io;
 use tracing::info;
 
 #[derive(Debug, Subcommand)]
 pub enum FindCommand {
     Find {
-        #[arg(long, short)]
+        #[arg(long, short, default_value = "")]
         name: String,
+        /// match `name` as a full text query: `show*`, `"the show"`, `a AND b`, `a OR b`, `a NOT b`.
+        #[arg(long, conflicts_with_all = ["glob", "regex"])]
+        fts: bool,
+        /// match `name` as a shell glob, `*S01E0?*.mkv`.
+        #[arg(long, conflicts_with = "regex")]
+        glob: bool,
+        /// match `name` as a regular expression, `(?i)1080p|2160p`.
+        #[arg(long)]
+        regex: bool,
+        /// match against the full path rather than the file name.
+        #[arg(long, short = 'f')]
+        full_path: bool,
         #[arg(long, short = 'p')]
         show_path: bool,
-        #[arg(long, short = 'd')]
+        #[arg(long, short = 'd', conflicts_with = "kind")]
         only_show_dir: bool,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+        #[arg(long, value_enum)]
+        sort: Option<Sort>,
+        #[arg(long, short = 'r')]
+        reverse: bool,
+        /// show at most this many files.
+        #[arg(long)]
+        limit: Option<u32>,
+        /// skip this many files first.
+        #[arg(long)]
+        offset: Option<u32>,
+        /// also show size, modification time and inode.
+        #[arg(long, short = 'l')]
+        long: bool,
+        #[arg(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+        /// columns of json, ndjson, csv and tsv output, comma separated.
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
     },
     Count,
 }
 
+/// what `find` narrows the matches down to, besides the name.
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// only files or only directories.
+    #[arg(long, value_enum)]
+    kind: Option<Kind>,
+    /// only files with one of these extensions.
+    #[arg(long = "ext", short = 'e')]
+    extensions: Vec<String>,
+    /// only files of one of these media categories.
+    #[arg(long = "category", short = 'c', value_enum)]
+    categories: Vec<Category>,
+    /// at least this big, `2G`, `500M`.
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<i64>,
+    /// at most this big.
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<i64>,
+    /// modified at or after, `2022-01-01` or `2022-01-01 08:00:00`.
+    #[arg(long, value_parser = parse_time)]
+    modified_after: Option<DateTime<Utc>>,
+    /// modified before.
+    #[arg(long, value_parser = parse_time)]
+    modified_before: Option<DateTime<Utc>>,
+    /// scanned at or after.
+    #[arg(long, value_parser = parse_time)]
+    scanned_after: Option<DateTime<Utc>>,
+    /// scanned before.
+    #[arg(long, value_parser = parse_time)]
+    scanned_before: Option<DateTime<Utc>>,
+    /// seen on this host, `nas` or `nas:liwei`.
+    #[arg(long)]
+    host: Option<String>,
+    /// only under this directory.
+    #[arg(long)]
+    path: Option<String>,
+    /// videos at least this wide, in pixels.
+    #[arg(long)]
+    min_width: Option<i64>,
+    /// videos at least this high, `1080`.
+    #[arg(long)]
+    min_height: Option<i64>,
+    /// at least this long, `90s`, `45m`, `1h30m`.
+    #[arg(long, value_parser = parse_duration)]
+    min_duration: Option<i64>,
+    /// at most this long.
+    #[arg(long, value_parser = parse_duration)]
+    max_duration: Option<i64>,
+    /// videos in this codec, `h264`, `hevc`.
+    #[arg(long)]
+    video_codec: Option<String>,
+    /// with audio in this codec, `aac`, `ac3`.
+    #[arg(long)]
+    audio_codec: Option<String>,
+    /// with an audio track in this language, `jpn`.
+    #[arg(long)]
+    audio_lang: Option<String>,
+    /// with subtitles in this language, `chi`.
+    #[arg(long)]
+    subtitle_lang: Option<String>,
+    /// songs by this artist or album artist, any part of the name.
+    #[arg(long)]
+    artist: Option<String>,
+    /// songs on this album, any part of its name.
+    #[arg(long)]
+    album: Option<String>,
+    /// songs or videos with this in their tagged title.
+    #[arg(long)]
+    title: Option<String>,
+    #[arg(long)]
+    genre: Option<String>,
+    /// songs released this year.
+    #[arg(long)]
+    year: Option<i64>,
+}
+
+impl FilterArgs {
+    fn apply(self, q: &mut SearchQuery) {
+        if self.kind.is_some() {
+            q.kind = self.kind;
+        }
+        q.extensions = self
+            .extensions
+            .iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .chain(
+                self.categories
+                    .iter()
+                    .flat_map(|c| c.extensions().iter().map(|e| e.to_string())),
+            )
+            .collect();
+        q.min_size = self.min_size;
+        q.max_size = self.max_size;
+        q.modified_after = self.modified_after;
+        q.modified_before = self.modified_before;
+        q.scanned_after = self.scanned_after;
+        q.scanned_before = self.scanned_before;
+        q.hostname = self.host;
+        q.path_prefix = self.path;
+        q.min_width = self.min_width;
+        q.min_height = self.min_height;
+        q.min_duration_ms = self.min_duration;
+        q.max_duration_ms = self.max_duration;
+        q.video_codec = self.video_codec;
+        q.audio_codec = self.audio_codec;
+        q.audio_lang = self.audio_lang;
+        q.subtitle_lang = self.subtitle_lang;
+        q.artist = self.artist;
+        q.album = self.album;
+        q.title = self.title;
+        q.genre = self.genre;
+        q.year = self.year;
+    }
+}
+
 impl FindCommand {
     pub async fn run(self, db: &mut impl Database, settings: &Settings) -> Result<()> {
         match self {
             Self::Find {
                 name,
+                fts,
+                glob,
+                regex,
+                full_path,
                 show_path,
                 only_show_dir,
+                filter,
+                sort,
+                reverse,
+                limit,
+                offset,
+                long,
+                format,
+                columns,
             } => {
-                let query = if only_show_dir {
-                    format!("select * from file where file_name like '%{name}%' and dir = 1;")
+                let name = normalize(&name);
+                let mode = if fts {
+                    SearchMode::FullText
+                } else if glob {
+                    SearchMode::Glob
+                } else if regex {
+                    SearchMode::Regex(Regex::new(&name)?)
                 } else {
-                    format!("select * from file where file_name like '%{name}%';")
+                    SearchMode::Substring
+                };
+                let mut query = SearchQuery {
+                    name,
+                    mode,
+                    full_path,
+                    kind: only_show_dir.then_some(Kind::Dir),
+                    sort,
+                    reverse,
+                    limit,
+                    offset,
+                    ..Default::default()
                 };
-                info!("query:{query}");
-                let files = db.query_file(&query).await?;
-                for f in &files {
-                    if !show_path {
-                        println!("{}", f.file_name);
-                    } else {
-                        println!("{}:({})", f.file_name, f.full_path);
-                    }
+                filter.apply(&mut query);
+                info!("query:{query:?}");
+                let mut files = db.search(&query);
+                let volumes = db.volumes().await?;
+                let mut printer = Printer::new(io::stdout().lock(), format, columns)
+                    .plain(show_path, long)
+                    .volumes(volumes);
+                while let Some(f) = files.try_next().await? {
+                    printer.print(&f)?;
                 }
-                // info!("{:?}", files);
+                printer.finish()?;
             }
             Self::Count => {
                 let file_count = db.file_count().await?;
@@ -0,0 +1,171 @@
+use crate::media_info::{MediaInfo, Tags, Track, TrackKind};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// extensions of flac files.
+pub const EXTENSIONS: &[&str] = &["flac"];
+
+/// only `STREAMINFO` and `VORBIS_COMMENT` are read, and a comment block with more than this in
+/// it holds a base64 `METADATA_BLOCK_PICTURE`, not text worth keeping.
+const MAX_BLOCK_SIZE: u32 = 1 << 20;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+
+/// duration out of `STREAMINFO` and tags out of `VORBIS_COMMENT`, `None` when `path` is no
+/// flac file.
+pub fn read(path: &Path) -> io::Result<Option<MediaInfo>> {
+    let mut file = fs::File::open(path)?;
+    let mut magic = [0; 10];
+    if file.read(&mut magic)? < 4 {
+        return Ok(None);
+    }
+    // some taggers put an id3v2 tag in front anyway.
+    let mut pos = 4;
+    if &magic[..3] == b"ID3" {
+        let size = magic[6..10]
+            .iter()
+            .fold(0, |n, &b| n << 7 | (b & 0x7f) as u64);
+        file.seek(SeekFrom::Start(10 + size))?;
+        file.read_exact(&mut magic[..4])?;
+        pos += 10 + size;
+    }
+    if &magic[..4] != b"fLaC" {
+        return Ok(None);
+    }
+
+    let mut info = MediaInfo::default();
+    let mut tags = Tags::default();
+    loop {
+        let mut header = [0; 4];
+        file.seek(SeekFrom::Start(pos))?;
+        if file.read_exact(&mut header).is_err() {
+            break;
+        }
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        pos += 4 + size as u64;
+
+        let kind = header[0] & 0x7f;
+        if matches!(kind, STREAMINFO | VORBIS_COMMENT) && size <= MAX_BLOCK_SIZE {
+            let mut block = vec![0; size as usize];
+            file.read_exact(&mut block)?;
+            if kind == STREAMINFO {
+                info.duration_ms = duration_ms(&block);
+            } else {
+                vorbis_comments(&block, &mut tags);
+            }
+        }
+
+        // the last block has the top bit set.
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    info.tags = (!tags.is_empty()).then_some(tags);
+    let track = Track {
+        number: 1,
+        kind: TrackKind::Audio,
+        codec: Some("flac".to_string()),
+        language: None,
+        name: None,
+    };
+    info.add_track(track, None, None);
+    Ok(Some(info))
+}
+
+/// total samples over the sample rate, both packed after the block and frame sizes.
+fn duration_ms(block: &[u8]) -> Option<i64> {
+    let b = block.get(10..18)?;
+    let sample_rate = (b[0] as u64) << 12 | (b[1] as u64) << 4 | (b[2] as u64) >> 4;
+    let samples =
+        ((b[3] & 0x0f) as u64) << 32 | u32::from_be_bytes(b[4..8].try_into().ok()?) as u64;
+    // zero samples is an unknown length.
+    if sample_rate == 0 || samples == 0 {
+        return None;
+    }
+    i64::try_from(samples * 1000 / sample_rate).ok()
+}
+
+/// the `KEY=value` comments after the vendor string, little endian unlike the rest of flac.
+fn vorbis_comments(block: &[u8], tags: &mut Tags) -> Option<()> {
+    let u32_at = |at: usize| -> Option<usize> {
+        Some(u32::from_le_bytes(block.get(at..at + 4)?.try_into().ok()?) as usize)
+    };
+    let mut pos = 4 + u32_at(0)?;
+    let count = u32_at(pos)?;
+    pos += 4;
+    for _ in 0..count {
+        let len = u32_at(pos)?;
+        let comment = block.get(pos + 4..pos + 4 + len)?;
+        pos += 4 + len;
+        if let Some((key, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            // the first of repeated fields wins, as with id3.
+            let mut field = Tags::default();
+            field.set(key, value);
+            *tags = std::mem::take(tags).or(field);
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::uuid_v4;
+
+    fn block(kind: u8, last: bool, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u32).to_be_bytes();
+        let kind = kind | if last { 0x80 } else { 0 };
+        [&[kind], &len[1..], body].concat()
+    }
+
+    fn comments(fields: &[&str]) -> Vec<u8> {
+        let mut b = 6u32.to_le_bytes().to_vec();
+        b.extend_from_slice(b"vendor");
+        b.extend((fields.len() as u32).to_le_bytes());
+        for f in fields {
+            b.extend((f.len() as u32).to_le_bytes());
+            b.extend_from_slice(f.as_bytes());
+        }
+        b
+    }
+
+    #[test]
+    fn test_read() {
+        let mut streaminfo = vec![0; 34];
+        // 44.1 khz, 2 channels, 16 bits, 3 minutes.
+        let samples: u64 = 44_100 * 180;
+        streaminfo[10..18]
+            .copy_from_slice(&((44_100u64 << 44) | (1 << 41) | (15 << 36) | samples).to_be_bytes());
+        let flac = [
+            b"fLaC".to_vec(),
+            block(STREAMINFO, false, &streaminfo),
+            block(1, false, &[0; 64]),
+            block(
+                VORBIS_COMMENT,
+                true,
+                &comments(&["TITLE=晴天", "artist=周杰伦", "ARTIST=Jay", "TRACKNUMBER=3"]),
+            ),
+            vec![0xff, 0xf8, 0, 0],
+        ]
+        .concat();
+        let path = std::env::temp_dir().join(format!("{}.flac", uuid_v4()));
+        fs::write(&path, flac).unwrap();
+        let info = read(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(info.duration_ms, Some(180_000));
+        assert_eq!(info.audio_codec.as_deref(), Some("flac"));
+        assert_eq!(
+            info.tags,
+            Some(Tags {
+                title: Some("晴天".to_string()),
+                artist: Some("周杰伦".to_string()),
+                track: Some(3),
+                ..Default::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// bytes read from each end of a file for its partial hash.
+pub const PARTIAL_SIZE: u64 = 64 * 1024;
+
+/// blake3 of the first and last `PARTIAL_SIZE` bytes, the whole file when it is not longer than
+/// both. enough to tell most files of the same size apart without reading them.
+pub fn partial_hash(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut hasher = blake3::Hasher::new();
+    if len <= 2 * PARTIAL_SIZE {
+        io::copy(&mut file, &mut hasher)?;
+    } else {
+        io::copy(&mut (&mut file).take(PARTIAL_SIZE), &mut hasher)?;
+        file.seek(SeekFrom::End(-(PARTIAL_SIZE as i64)))?;
+        io::copy(&mut file, &mut hasher)?;
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// blake3 of the whole file.
+pub fn full_hash(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::uuid_v4;
+
+    #[test]
+    fn test_hash() {
+        let dir = std::env::temp_dir().join(uuid_v4());
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b, small) = (dir.join("a"), dir.join("b"), dir.join("small"));
+        let mut data = vec![7u8; 3 * PARTIAL_SIZE as usize];
+        fs::write(&a, &data).unwrap();
+        // same ends, a different middle.
+        data[PARTIAL_SIZE as usize + 1] = 8;
+        fs::write(&b, &data).unwrap();
+        fs::write(&small, b"small").unwrap();
+
+        assert_eq!(partial_hash(&a).unwrap(), partial_hash(&b).unwrap());
+        assert_ne!(full_hash(&a).unwrap(), full_hash(&b).unwrap());
+        assert_eq!(partial_hash(&small).unwrap(), full_hash(&small).unwrap());
+        assert_eq!(
+            full_hash(&small).unwrap(),
+            blake3::hash(b"small").to_hex().to_string()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
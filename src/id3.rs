@@ -0,0 +1,571 @@
+use crate::media_info::{MediaInfo, Tags, Track, TrackKind};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// extensions of mpeg audio files, tagged with id3.
+pub const EXTENSIONS: &[&str] = &["mp3"];
+
+/// the id3v2 tag is read into memory before its frames are walked. embedded `APIC` pictures
+/// are what make a tag big, past this there is little but pictures left to find.
+const MAX_TAG_SIZE: u64 = 16 << 20;
+
+/// how far past the tag to look for the first mpeg frame.
+const MAX_FRAME_SEARCH: usize = 64 << 10;
+
+/// the genres of id3v1, which mp4 `gnre` atoms and id3v2 `(17)` genres number as well.
+pub const GENRES: &[&str] = &[
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "Alternative Rock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychedelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+/// genre number `n` of id3v1.
+pub fn genre(n: usize) -> Option<String> {
+    GENRES.get(n).map(|g| g.to_string())
+}
+
+/// tags and duration of an mp3, `None` when it has neither tags nor an mpeg audio frame.
+pub fn read(path: &Path) -> io::Result<Option<MediaInfo>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut header = [0; 10];
+    let mut v2 = None;
+    let mut audio_start = 0;
+    if len >= 10 {
+        file.read_exact(&mut header)?;
+        if let Some(size) = tag_size(&header) {
+            audio_start = 10 + size;
+            if size <= MAX_TAG_SIZE && audio_start <= len {
+                let mut body = vec![0; size as usize];
+                file.read_exact(&mut body)?;
+                v2 = parse_v2(&header, &body);
+            }
+        }
+    }
+
+    let mut v1 = None;
+    let mut audio_end = len;
+    if len >= audio_start + 128 {
+        let mut tail = [0; 128];
+        file.seek(SeekFrom::Start(len - 128))?;
+        file.read_exact(&mut tail)?;
+        v1 = parse_v1(&tail);
+        if v1.is_some() {
+            audio_end -= 128;
+        }
+    }
+
+    file.seek(SeekFrom::Start(audio_start.min(len)))?;
+    let mut frames = Vec::with_capacity(MAX_FRAME_SEARCH);
+    file.take(MAX_FRAME_SEARCH as u64)
+        .read_to_end(&mut frames)?;
+    let audio_len = audio_end.saturating_sub(audio_start);
+    let frame_duration =
+        first_frame(&frames).map(|(at, f)| f.duration_ms(&frames[at..], audio_len));
+
+    let (v2_tags, tlen) = v2.unwrap_or_default();
+    let tags = v2_tags.or(v1.unwrap_or_default());
+    if tags.is_empty() && frame_duration.is_none() {
+        return Ok(None);
+    }
+
+    let mut info = MediaInfo {
+        duration_ms: tlen.or(frame_duration.flatten()),
+        tags: (!tags.is_empty()).then_some(tags),
+        ..Default::default()
+    };
+    let track = Track {
+        number: 1,
+        kind: TrackKind::Audio,
+        codec: Some("mp3".to_string()),
+        language: None,
+        name: None,
+    };
+    info.add_track(track, None, None);
+    Ok(Some(info))
+}
+
+/// size of the id3v2 tag `header` starts, without the header and footer.
+fn tag_size(header: &[u8; 10]) -> Option<u64> {
+    if &header[..3] != b"ID3" || header[6..10].iter().any(|b| b & 0x80 != 0) {
+        return None;
+    }
+    // a footer repeats the header at the end of the tag.
+    let footer = if header[3] == 4 && header[5] & 0x10 != 0 {
+        10
+    } else {
+        0
+    };
+    Some(syncsafe(&header[6..10]) as u64 + footer)
+}
+
+/// 7 bits in every byte, so a tag never looks like an mpeg frame sync.
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter().fold(0, |n, &b| n << 7 | (b & 0x7f) as u32)
+}
+
+/// undo the unsynchronisation that put a zero after every `0xff`.
+fn resync(buf: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buf.len());
+    for (i, &b) in buf.iter().enumerate() {
+        if b == 0 && i > 0 && buf[i - 1] == 0xff {
+            continue;
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// tags and `TLEN` out of the body of an id3v2.2, 2.3 or 2.4 tag.
+fn parse_v2(header: &[u8; 10], body: &[u8]) -> Option<(Tags, Option<i64>)> {
+    let version = header[3];
+    let flags = header[5];
+    let unsynced = flags & 0x80 != 0;
+    // before 2.4 the whole tag is unsynchronised at once, in 2.4 frame by frame.
+    let body = if unsynced && version < 4 {
+        resync(body)
+    } else {
+        body.to_vec()
+    };
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 && version >= 3 {
+        pos = match version {
+            3 => u32::from_be_bytes(body.get(..4)?.try_into().ok()?) as usize + 4,
+            _ => syncsafe(body.get(..4)?) as usize,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut tags = Tags::default();
+    let mut tlen = None;
+    while let Some(frame_header) = body.get(pos..pos + header_len) {
+        let id = &frame_header[..id_len];
+        // padding.
+        if id[0] == 0 {
+            break;
+        }
+        let size = match version {
+            2 => u32::from_be_bytes([0, frame_header[3], frame_header[4], frame_header[5]]),
+            3 => u32::from_be_bytes(frame_header[4..8].try_into().ok()?),
+            _ => syncsafe(&frame_header[4..8]),
+        } as usize;
+        let start = pos + header_len;
+        // a frame that runs past the tag, the ones before it are still good.
+        let Some(data) = body.get(start..start + size) else {
+            break;
+        };
+        pos = start + size;
+
+        let mut data = data.to_vec();
+        if version >= 3 {
+            let frame_flags = frame_header[9];
+            if version == 3 {
+                // compressed or encrypted frames are skipped, grouping adds a byte.
+                if frame_flags & 0xc0 != 0 {
+                    continue;
+                }
+                if frame_flags & 0x20 != 0 {
+                    data.drain(..1.min(data.len()));
+                }
+            } else {
+                if frame_flags & 0x0c != 0 {
+                    continue;
+                }
+                let skip = (frame_flags & 0x40 != 0) as usize + 4 * (frame_flags & 0x01) as usize;
+                data.drain(..skip.min(data.len()));
+                if unsynced || frame_flags & 0x02 != 0 {
+                    data = resync(&data);
+                }
+            }
+        }
+
+        let key = match id {
+            b"TIT2" | b"TT2" => "TITLE",
+            b"TPE1" | b"TP1" => "ARTIST",
+            b"TALB" | b"TAL" => "ALBUM",
+            b"TPE2" | b"TP2" => "ALBUMARTIST",
+            b"TCON" | b"TCO" => "GENRE",
+            b"TRCK" | b"TRK" => "TRACKNUMBER",
+            b"TYER" | b"TYE" | b"TDRC" => "YEAR",
+            b"TLEN" | b"TLE" => {
+                tlen = text(&data).and_then(|t| t.trim().parse().ok());
+                continue;
+            }
+            _ => continue,
+        };
+        let Some(value) = text(&data) else {
+            continue;
+        };
+        let value = if key == "GENRE" {
+            v2_genre(&value)
+        } else {
+            value
+        };
+        tags.set(key, &value);
+    }
+
+    Some((tags, tlen))
+}
+
+/// the first value of a text frame, in whichever of the four encodings it says it uses.
+fn text(data: &[u8]) -> Option<String> {
+    let (&encoding, s) = data.split_first()?;
+    let s = match encoding {
+        0 => s.iter().map(|&b| b as char).collect(),
+        1 | 2 => {
+            let mut big_endian = encoding == 2;
+            let mut s = s;
+            match s {
+                [0xfe, 0xff, rest @ ..] => (big_endian, s) = (true, rest),
+                [0xff, 0xfe, rest @ ..] => (big_endian, s) = (false, rest),
+                _ => {}
+            }
+            let units: Vec<u16> = s
+                .chunks_exact(2)
+                .map(|c| {
+                    if big_endian {
+                        u16::from_be_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_le_bytes([c[0], c[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(s).into_owned(),
+        _ => return None,
+    };
+    // 2.4 separates several values with a nul.
+    s.split('\0').next().map(str::to_string)
+}
+
+/// `Rock` of `(17)`, `17` and `(17)Rock`.
+fn v2_genre(value: &str) -> String {
+    let (number, rest) = match value.strip_prefix('(').and_then(|v| v.split_once(')')) {
+        Some((n, rest)) => (n, rest),
+        None => (value, ""),
+    };
+    if !rest.trim().is_empty() {
+        return rest.to_string();
+    }
+    match number {
+        "RX" => "Remix".to_string(),
+        "CR" => "Cover".to_string(),
+        n => n
+            .parse()
+            .ok()
+            .and_then(genre)
+            .unwrap_or_else(|| value.to_string()),
+    }
+}
+
+/// the 128 byte id3v1 tag at the end of the file, with the track of v1.1.
+fn parse_v1(tail: &[u8; 128]) -> Option<Tags> {
+    if &tail[..3] != b"TAG" {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| -> String {
+        tail[range].iter().map(|&b| b as char).collect()
+    };
+
+    let mut tags = Tags::default();
+    tags.set("TITLE", &field(3..33));
+    tags.set("ARTIST", &field(33..63));
+    tags.set("ALBUM", &field(63..93));
+    tags.set("YEAR", &field(93..97));
+    if tail[125] == 0 && tail[126] != 0 {
+        tags.track = Some(tail[126] as i64);
+    }
+    tags.genre = genre(tail[127] as usize);
+    Some(tags)
+}
+
+/// the fields of an mpeg audio layer iii frame header needed for the duration.
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    mpeg1: bool,
+    mono: bool,
+    /// kbit/s.
+    bitrate: u32,
+    sample_rate: u32,
+}
+
+impl Frame {
+    fn parse(b: &[u8]) -> Option<Frame> {
+        let h = u32::from_be_bytes(b.get(..4)?.try_into().ok()?);
+        if h >> 21 != 0x7ff || (h >> 17) & 3 != 1 {
+            return None;
+        }
+        let version = (h >> 19) & 3;
+        let mpeg1 = version == 3;
+        let bitrates: [u32; 15] = if mpeg1 {
+            [
+                0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+            ]
+        } else {
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]
+        };
+        let bitrate = *bitrates.get(((h >> 12) & 0xf) as usize)?;
+        let sample_rate = [44100, 48000, 32000].get(((h >> 10) & 3) as usize)?
+            / match version {
+                3 => 1,
+                2 => 2,
+                0 => 4,
+                _ => return None,
+            };
+        Some(Frame {
+            mpeg1,
+            mono: (h >> 6) & 3 == 3,
+            bitrate,
+            sample_rate,
+        })
+    }
+
+    fn samples(&self) -> u64 {
+        if self.mpeg1 {
+            1152
+        } else {
+            576
+        }
+    }
+
+    /// from the frame count of a `Xing`, `Info` or `VBRI` header in the frame `b` starts with,
+    /// or from the bitrate and `audio_len` when there is none.
+    fn duration_ms(&self, b: &[u8], audio_len: u64) -> Option<i64> {
+        let side_info = match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        };
+        let xing = 4 + side_info;
+        let frames = match b.get(xing..xing + 4)? {
+            b"Xing" | b"Info" if b.get(xing + 7)? & 1 != 0 => Some(u32::from_be_bytes(
+                b.get(xing + 8..xing + 12)?.try_into().ok()?,
+            )),
+            _ if b.get(36..40) == Some(b"VBRI") => {
+                Some(u32::from_be_bytes(b.get(50..54)?.try_into().ok()?))
+            }
+            _ => None,
+        };
+
+        let ms = match frames {
+            Some(n) => n as u64 * self.samples() * 1000 / self.sample_rate as u64,
+            None if self.bitrate > 0 => audio_len * 8 / self.bitrate as u64,
+            None => return None,
+        };
+        i64::try_from(ms).ok()
+    }
+}
+
+/// offset and header of the first frame in `buf`.
+fn first_frame(buf: &[u8]) -> Option<(usize, Frame)> {
+    (0..buf.len().saturating_sub(3))
+        .filter(|&at| buf[at] == 0xff)
+        .find_map(|at| Frame::parse(&buf[at..]).map(|f| (at, f)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::uuid_v4;
+
+    fn frame(id: &[u8; 4], data: &[u8], version: u8) -> Vec<u8> {
+        let size = data.len() as u32;
+        let size = match version {
+            4 => (0..4)
+                .rev()
+                .fold(0, |n, i| n << 8 | (size >> (7 * i)) & 0x7f),
+            _ => size,
+        };
+        [id.as_slice(), &size.to_be_bytes(), &[0, 0], data].concat()
+    }
+
+    fn tag(version: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() as u32;
+        let size: Vec<u8> = (0..4)
+            .rev()
+            .map(|i| ((size >> (7 * i)) & 0x7f) as u8)
+            .collect();
+        [b"ID3".as_slice(), &[version, 0, 0], &size, &body, &[0; 16]].concat()
+    }
+
+    /// a 128 kbit/s mpeg 1 layer iii stereo frame at 44.1 khz, with a `Xing` header.
+    fn xing_frame(frames: u32) -> Vec<u8> {
+        let mut f = vec![0; 417];
+        f[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        f[36..40].copy_from_slice(b"Xing");
+        f[43] = 1;
+        f[44..48].copy_from_slice(&frames.to_be_bytes());
+        f
+    }
+
+    fn read_bytes(bytes: &[u8]) -> Option<MediaInfo> {
+        let path = std::env::temp_dir().join(format!("{}.mp3", uuid_v4()));
+        fs::write(&path, bytes).unwrap();
+        let info = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        info
+    }
+
+    #[test]
+    fn test_read_v2() {
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("七里香".encode_utf16().flat_map(|u| u.to_le_bytes()))
+            .collect();
+        let v4 = tag(
+            4,
+            &[
+                frame(b"TIT2", &[[1].as_slice(), &utf16].concat(), 4),
+                frame(b"TPE1", "\x03周杰伦\0Jay".as_bytes(), 4),
+                frame(b"TCON", b"\x00(17)", 4),
+                frame(b"TRCK", b"\x003/10", 4),
+                frame(b"TDRC", b"\x002004-08-03", 4),
+            ],
+        );
+        let mp3 = [v4, xing_frame(1000), vec![0; 4096]].concat();
+        let info = read_bytes(&mp3).unwrap();
+        assert_eq!(
+            info.tags,
+            Some(Tags {
+                title: Some("七里香".to_string()),
+                artist: Some("周杰伦".to_string()),
+                genre: Some("Rock".to_string()),
+                track: Some(3),
+                year: Some(2004),
+                ..Default::default()
+            })
+        );
+        // 1000 frames of 1152 samples.
+        assert_eq!(info.duration_ms, Some(26_122));
+        assert_eq!(info.audio_codec.as_deref(), Some("mp3"));
+
+        let v3 = tag(
+            3,
+            &[
+                frame(b"TALB", b"\x00Album", 3),
+                frame(b"TLEN", b"\x00215000", 3),
+            ],
+        );
+        let info = read_bytes(&[v3, xing_frame(1000)].concat()).unwrap();
+        assert_eq!(info.tags.unwrap().album.as_deref(), Some("Album"));
+        assert_eq!(info.duration_ms, Some(215_000));
+
+        let mut broken = frame(b"TPE1", b"\x00Artist", 3);
+        broken[4..8].copy_from_slice(&1000u32.to_be_bytes());
+        let v3 = tag(3, &[frame(b"TALB", b"\x00Album", 3), broken]);
+        let tags = read_bytes(&[v3, xing_frame(1000)].concat())
+            .unwrap()
+            .tags
+            .unwrap();
+        assert_eq!(tags.album.as_deref(), Some("Album"));
+        assert_eq!(tags.artist, None);
+    }
+
+    #[test]
+    fn test_read_v1() {
+        let mut v1 = [0u8; 128];
+        v1[..3].copy_from_slice(b"TAG");
+        v1[3..8].copy_from_slice(b"Title");
+        v1[33..39].copy_from_slice(b"Artist");
+        v1[93..97].copy_from_slice(b"1999");
+        v1[126] = 7;
+        v1[127] = 8;
+        // a cbr file without a xing header, one second at 128 kbit/s.
+        let mut frame = xing_frame(0);
+        frame[36..40].copy_from_slice(&[0; 4]);
+        let audio: Vec<u8> = frame.iter().copied().cycle().take(16_000).collect();
+        let info = read_bytes(&[audio, v1.to_vec()].concat()).unwrap();
+        let tags = info.tags.unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Title"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!((tags.track, tags.year), (Some(7), Some(1999)));
+        assert_eq!(tags.genre.as_deref(), Some("Jazz"));
+        assert_eq!(info.duration_ms, Some(1000));
+
+        assert!(read_bytes(&[0; 1024]).is_none());
+        assert_eq!(v2_genre("(4)Disco Inferno"), "Disco Inferno");
+        assert_eq!(v2_genre("255"), "255");
+    }
+}
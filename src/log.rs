@@ -2,17 +2,24 @@ use time::macros::format_description;
 use time::UtcOffset;
 use tracing_subscriber::fmt::time::OffsetTime;
 
+/// log at debug for tests, unless `RUST_LOG` says otherwise.
+#[cfg(test)]
 pub fn log_init() {
+    log_init_at("find_videos=debug");
+}
+
+/// log at `default`, unless `RUST_LOG` says otherwise.
+pub fn log_init_at(default: &str) {
     // 修改时区及时间格式
     let local_time = OffsetTime::new(
         UtcOffset::from_hms(8, 0, 0).unwrap(),
         format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"),
     );
 
-    tracing_subscriber::fmt()
+    let _ = tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "find_videos=debug".to_string()),
+            std::env::var("RUST_LOG").unwrap_or_else(|_| default.to_string()),
         ))
         .with_timer(local_time)
-        .init();
+        .try_init();
 }
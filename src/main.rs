@@ -1,12 +1,30 @@
+mod chinese;
 mod cli;
 mod database;
+mod dupes;
 mod event;
+mod exclude;
 mod file;
 mod find;
+mod flac;
+mod hash;
+mod id3;
 mod log;
+mod media;
+mod media_info;
+mod mkv;
+mod mp4;
+mod output;
+mod progress;
+mod regexp;
+mod report;
 mod scan;
+mod scans;
+mod search;
 mod settings;
 mod util;
+mod volume;
+mod watch;
 
 use clap::Parser;
 use eyre::Result;
@@ -14,9 +32,8 @@ use eyre::Result;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    log::log_init();
-
     let args = cli::Args::parse();
+    log::log_init_at(args.command.log_filter());
 
     // info!("start find videos and args:{:?}.", args);
 
@@ -0,0 +1,90 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Video,
+    Audio,
+    Subtitle,
+    Image,
+    Document,
+}
+
+impl Category {
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::Video => &[
+                "mp4", "m4v", "mkv", "mov", "avi", "wmv", "flv", "webm", "mpg", "mpeg", "ts",
+                "m2ts", "vob", "rm", "rmvb", "3gp",
+            ],
+            Self::Audio => &[
+                "mp3", "flac", "wav", "aac", "m4a", "ogg", "opus", "wma", "ape", "aiff",
+            ],
+            Self::Subtitle => &["srt", "ass", "ssa", "sub", "idx", "vtt", "sup"],
+            Self::Image => &[
+                "jpg", "jpeg", "png", "gif", "bmp", "webp", "heic", "tif", "tiff",
+            ],
+            Self::Document => &[
+                "pdf", "epub", "mobi", "txt", "md", "doc", "docx", "xls", "xlsx", "ppt", "pptx",
+            ],
+        }
+    }
+}
+
+/// lower case extension of a file name without the leading dot.
+pub fn extension(file_name: &str) -> Option<String> {
+    Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+/// decides which files a scan keeps, by extension and ignoring case.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    extensions: HashSet<String>,
+}
+
+impl FileFilter {
+    pub fn new(categories: &[Category], extensions: &[String]) -> Self {
+        let extensions = categories
+            .iter()
+            .flat_map(|c| c.extensions().iter().map(|e| e.to_string()))
+            .chain(
+                extensions
+                    .iter()
+                    .map(|e| e.trim_start_matches('.').to_lowercase()),
+            )
+            .collect();
+
+        Self { extensions }
+    }
+
+    pub fn matches(&self, file_name: &str) -> bool {
+        extension(file_name)
+            .map(|e| self.extensions.contains(&e))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_filter() {
+        let filter = FileFilter::new(&[Category::Video], &[".SRT".to_string()]);
+        assert!(filter.matches("movie.MKV"));
+        assert!(filter.matches("clip.m4v"));
+        assert!(filter.matches("movie.zh.srt"));
+        assert!(!filter.matches("song.flac"));
+        assert!(!filter.matches("mkv"));
+
+        let filter = FileFilter::new(&[Category::Audio], &[]);
+        assert!(filter.matches("song.FLAC"));
+        assert!(!filter.matches("movie.mov"));
+    }
+}
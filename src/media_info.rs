@@ -0,0 +1,244 @@
+use crate::media::extension;
+use crate::{flac, id3, mkv, mp4};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// what `scan` reads out of a video besides its name, all of it optional as files often
+/// leave things out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaInfo {
+    /// `File::id` this is about.
+    pub file_id: String,
+    pub duration_ms: Option<i64>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    /// codec of the first video track, `h264`, `hevc`.
+    pub video_codec: Option<String>,
+    /// codec of the first audio track, `aac`, `ac3`.
+    pub audio_codec: Option<String>,
+    /// when the recording was made, as the file says.
+    pub created: Option<DateTime<Utc>>,
+    /// title the file carries, besides its name.
+    pub title: Option<String>,
+    pub tracks: Vec<Track>,
+    /// tags of music files, see `Tags`.
+    pub tags: Option<Tags>,
+}
+
+/// what id3, vorbis comments and itunes atoms say about a song.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    /// number on the album, without the `/12` some tags add.
+    pub track: Option<i64>,
+    pub year: Option<i64>,
+}
+
+impl Tags {
+    /// set `value` as the tag named `key` in vorbis comment style, `ARTIST`, `TRACKNUMBER`,
+    /// and the like, ignoring the ones it does not know.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if value.is_empty() {
+            return;
+        }
+        let text = Some(value.to_string());
+        match key.to_uppercase().as_str() {
+            "TITLE" => self.title = text,
+            "ARTIST" => self.artist = text,
+            "ALBUM" => self.album = text,
+            "ALBUMARTIST" | "ALBUM ARTIST" => self.album_artist = text,
+            "GENRE" => self.genre = text,
+            "TRACKNUMBER" => self.track = leading_number(value),
+            "DATE" | "YEAR" => self.year = leading_number(value),
+            _ => {}
+        }
+    }
+
+    /// fill what is missing here from `other`.
+    pub fn or(self, other: Tags) -> Tags {
+        Tags {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            album: self.album.or(other.album),
+            album_artist: self.album_artist.or(other.album_artist),
+            genre: self.genre.or(other.genre),
+            track: self.track.or(other.track),
+            year: self.year.or(other.year),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Tags::default()
+    }
+}
+
+/// the number `s` starts with, `3` of `3/12` and `2019` of `2019-05-01`.
+fn leading_number(s: &str) -> Option<i64> {
+    let digits: String = s
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Subtitle,
+}
+
+impl TrackKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Subtitle => "subtitle",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    /// number of the track in its file.
+    pub number: i64,
+    pub kind: TrackKind,
+    pub codec: Option<String>,
+    /// iso 639-2 code, `jpn`, `chi`, or a bcp 47 tag when the file only has that.
+    pub language: Option<String>,
+    /// what the track is called in the file, `Director's commentary`.
+    pub name: Option<String>,
+}
+
+impl MediaInfo {
+    /// take the size and codecs of the first video and audio tracks as those of the file.
+    pub fn add_track(&mut self, track: Track, width: Option<i64>, height: Option<i64>) {
+        match track.kind {
+            TrackKind::Video if self.video_codec.is_none() => {
+                self.video_codec = track.codec.clone();
+                self.width = width;
+                self.height = height;
+            }
+            TrackKind::Audio if self.audio_codec.is_none() => {
+                self.audio_codec = track.codec.clone();
+            }
+            _ => {}
+        }
+        self.tracks.push(track);
+    }
+}
+
+/// iso 639-2 languages with both a bibliographic and a terminology code, so `chi` finds
+/// tracks tagged `zho` and the other way round.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("alb", "sqi"),
+    ("arm", "hye"),
+    ("baq", "eus"),
+    ("bur", "mya"),
+    ("chi", "zho"),
+    ("cze", "ces"),
+    ("dut", "nld"),
+    ("fre", "fra"),
+    ("geo", "kat"),
+    ("ger", "deu"),
+    ("gre", "ell"),
+    ("ice", "isl"),
+    ("mac", "mkd"),
+    ("mao", "mri"),
+    ("may", "msa"),
+    ("per", "fas"),
+    ("rum", "ron"),
+    ("slo", "slk"),
+    ("tib", "bod"),
+    ("wel", "cym"),
+];
+
+/// the lower case codes a track of language `lang` may be tagged with.
+pub fn language_codes(lang: &str) -> Vec<String> {
+    let lang = lang.to_lowercase();
+    let alias = LANGUAGE_ALIASES
+        .iter()
+        .find_map(|&(b, t)| match lang.as_str() {
+            l if l == b => Some(t),
+            l if l == t => Some(b),
+            _ => None,
+        });
+    [Some(lang.as_str()), alias]
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect()
+}
+
+/// extensions there is a reader for, lower case and without the dot.
+pub fn readable_extensions() -> impl Iterator<Item = &'static str> {
+    [
+        mp4::EXTENSIONS,
+        mkv::EXTENSIONS,
+        id3::EXTENSIONS,
+        flac::EXTENSIONS,
+    ]
+    .into_iter()
+    .flatten()
+    .copied()
+}
+
+/// read the media info of `path`, an empty one when the file does not parse.
+pub fn read(path: &Path) -> io::Result<MediaInfo> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let info = match extension(&name).as_deref() {
+        Some(e) if mp4::EXTENSIONS.contains(&e) => mp4::read(path)?,
+        Some(e) if mkv::EXTENSIONS.contains(&e) => mkv::read(path)?,
+        Some(e) if id3::EXTENSIONS.contains(&e) => id3::read(path)?,
+        Some(e) if flac::EXTENSIONS.contains(&e) => flac::read(path)?,
+        _ => None,
+    };
+
+    Ok(info.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_language_codes() {
+        assert_eq!(language_codes("CHI"), vec!["chi", "zho"]);
+        assert_eq!(language_codes("zho"), vec!["zho", "chi"]);
+        assert_eq!(language_codes("jpn"), vec!["jpn"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut tags = Tags::default();
+        tags.set("Artist", "周杰伦 ");
+        tags.set("TRACKNUMBER", "3/12");
+        tags.set("date", "2003-07-31");
+        tags.set("COMMENT", "ignored");
+        tags.set("ALBUM", "\0");
+        let other = Tags {
+            album: Some("叶惠美".to_string()),
+            track: Some(9),
+            ..Default::default()
+        };
+        assert_eq!(
+            tags.or(other),
+            Tags {
+                artist: Some("周杰伦".to_string()),
+                album: Some("叶惠美".to_string()),
+                track: Some(3),
+                year: Some(2003),
+                ..Default::default()
+            }
+        );
+    }
+}
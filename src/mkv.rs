@@ -0,0 +1,391 @@
+use crate::media_info::{MediaInfo, Track, TrackKind};
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// extensions of matroska files.
+pub const EXTENSIONS: &[&str] = &["mkv", "mka", "mks", "webm"];
+
+/// an `Info` or `Tracks` body is read whole, and an ebml size of up to eight bytes can claim
+/// anything. real ones are a few kilobytes, sixteen megabytes is only a corrupt size field.
+const MAX_ELEMENT_SIZE: u64 = 16 << 20;
+
+/// seconds from 1970-01-01 to 2001-01-01, where matroska dates count from.
+const EPOCH_OFFSET: i64 = 978_307_200;
+
+const EBML: u64 = 0x1A45_DFA3;
+const DOC_TYPE: u64 = 0x4282;
+const SEGMENT: u64 = 0x1853_8067;
+const CLUSTER: u64 = 0x1F43_B675;
+
+const INFO: u64 = 0x1549_A966;
+const TIMESTAMP_SCALE: u64 = 0x2A_D7B1;
+const DURATION: u64 = 0x4489;
+const TITLE: u64 = 0x7BA9;
+const DATE_UTC: u64 = 0x4461;
+
+const TRACKS: u64 = 0x1654_AE6B;
+const TRACK_ENTRY: u64 = 0xAE;
+const TRACK_NUMBER: u64 = 0xD7;
+const TRACK_TYPE: u64 = 0x83;
+const CODEC_ID: u64 = 0x86;
+const NAME: u64 = 0x536E;
+const LANGUAGE: u64 = 0x22_B59C;
+const LANGUAGE_BCP47: u64 = 0x22_B59D;
+const VIDEO: u64 = 0xE0;
+const PIXEL_WIDTH: u64 = 0xB0;
+const PIXEL_HEIGHT: u64 = 0xBA;
+
+/// duration, title, and codec, size and language of every track out of the segment info and
+/// tracks of `path`, `None` when it is no matroska file.
+pub fn read(path: &Path) -> io::Result<Option<MediaInfo>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let Some((EBML, Some(size), header)) = header_at(&mut file, 0, len)? else {
+        return Ok(None);
+    };
+    let Some(body) = body_at(&mut file, header, size)? else {
+        return Ok(None);
+    };
+    let doc_type = Elements(&body).find(|(id, _)| *id == DOC_TYPE);
+    if !matches!(
+        doc_type.map(|(_, v)| text(v)).as_deref(),
+        Some("matroska" | "webm")
+    ) {
+        return Ok(None);
+    }
+
+    let mut pos = header + size;
+    let Some((SEGMENT, size, header)) = header_at(&mut file, pos, len)? else {
+        return Ok(None);
+    };
+    // a live recording leaves the size of the segment open.
+    let end = size.map(|s| pos + header + s).unwrap_or(len).min(len);
+    pos += header;
+
+    let mut info = MediaInfo::default();
+    let (mut has_info, mut has_tracks) = (false, false);
+    while pos < end && !(has_info && has_tracks) {
+        let Some((id, Some(size), header)) = header_at(&mut file, pos, end)? else {
+            break;
+        };
+        match id {
+            INFO => {
+                if let Some(body) = body_at(&mut file, pos + header, size)? {
+                    parse_info(&body, &mut info);
+                }
+                has_info = true;
+            }
+            TRACKS => {
+                if let Some(body) = body_at(&mut file, pos + header, size)? {
+                    parse_tracks(&body, &mut info);
+                }
+                has_tracks = true;
+            }
+            // clusters hold the frames, info and tracks come before them in about every file.
+            CLUSTER if has_info || has_tracks => break,
+            _ => {}
+        }
+        pos += header + size;
+    }
+
+    Ok(Some(info))
+}
+
+/// id, size if known and header length of the element at `pos`.
+fn header_at(
+    r: &mut (impl Read + Seek),
+    pos: u64,
+    end: u64,
+) -> io::Result<Option<(u64, Option<u64>, u64)>> {
+    let mut buf = [0; 12];
+    let n = end.saturating_sub(pos).min(buf.len() as u64) as usize;
+    r.seek(SeekFrom::Start(pos))?;
+    r.read_exact(&mut buf[..n])?;
+    Ok(element_header(&buf[..n]).map(|(id, size, len)| (id, size, len as u64)))
+}
+
+fn body_at(r: &mut (impl Read + Seek), pos: u64, size: u64) -> io::Result<Option<Vec<u8>>> {
+    if size > MAX_ELEMENT_SIZE {
+        return Ok(None);
+    }
+    let mut body = vec![0; size as usize];
+    r.seek(SeekFrom::Start(pos))?;
+    r.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// a variable length integer, with its length marker kept for ids and dropped for sizes,
+/// and how many bytes it took.
+fn vint(buf: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *buf.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let first = if keep_marker {
+        first
+    } else {
+        first & (0xff_u16 >> len) as u8
+    };
+    let value = buf
+        .get(1..len)?
+        .iter()
+        .fold(first as u64, |v, b| (v << 8) | *b as u64);
+    Some((value, len))
+}
+
+/// id, size, `None` when unknown, and header length of the element `buf` starts with.
+fn element_header(buf: &[u8]) -> Option<(u64, Option<u64>, usize)> {
+    let (id, id_len) = vint(buf, true)?;
+    let (size, size_len) = vint(&buf[id_len..], false)?;
+    // all value bits set means the size is unknown.
+    let unknown = size == (1 << (7 * size_len)) - 1;
+    Some((id, (!unknown).then_some(size), id_len + size_len))
+}
+
+/// the elements in a buffer, as id and data.
+struct Elements<'a>(&'a [u8]);
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (u64, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.0;
+        let (id, size, header) = element_header(buf)?;
+        let end = match size {
+            Some(size) => header.checked_add(usize::try_from(size).ok()?)?,
+            None => buf.len(),
+        };
+        let data = buf.get(header..end)?;
+        self.0 = &buf[end..];
+        Some((id, data))
+    }
+}
+
+fn uint(data: &[u8]) -> Option<u64> {
+    (data.len() <= 8).then(|| data.iter().fold(0, |v, b| (v << 8) | *b as u64))
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn parse_info(body: &[u8], info: &mut MediaInfo) {
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    for (id, data) in Elements(body) {
+        match id {
+            TIMESTAMP_SCALE => scale = uint(data).unwrap_or(scale),
+            DURATION => duration = float(data),
+            TITLE => info.title = Some(text(data)).filter(|t| !t.is_empty()),
+            DATE_UTC if data.len() == 8 => {
+                let ns = i64::from_be_bytes(data.try_into().unwrap());
+                info.created =
+                    Some(Utc.timestamp_nanos(ns) + chrono::Duration::seconds(EPOCH_OFFSET));
+            }
+            _ => {}
+        }
+    }
+
+    // the duration counts in units of `scale` nanoseconds.
+    info.duration_ms = duration.map(|d| (d * scale as f64 / 1_000_000.0) as i64);
+}
+
+fn parse_tracks(body: &[u8], info: &mut MediaInfo) {
+    for (n, (_, entry)) in Elements(body)
+        .filter(|(id, _)| *id == TRACK_ENTRY)
+        .enumerate()
+    {
+        let mut number = n as i64 + 1;
+        let (mut kind, mut codec, mut name) = (None, None, None);
+        let (mut language, mut bcp47) = (None, None);
+        let (mut width, mut height) = (None, None);
+        for (id, data) in Elements(entry) {
+            match id {
+                TRACK_NUMBER => number = uint(data).map(|v| v as i64).unwrap_or(number),
+                TRACK_TYPE => {
+                    kind = match uint(data) {
+                        Some(1) => Some(TrackKind::Video),
+                        Some(2) => Some(TrackKind::Audio),
+                        Some(17) => Some(TrackKind::Subtitle),
+                        _ => None,
+                    }
+                }
+                CODEC_ID => codec = Some(codec_name(&text(data))),
+                NAME => name = Some(text(data)).filter(|n| !n.is_empty()),
+                LANGUAGE => language = Some(text(data)),
+                LANGUAGE_BCP47 => bcp47 = Some(text(data)),
+                VIDEO => {
+                    for (id, data) in Elements(data) {
+                        match id {
+                            PIXEL_WIDTH => width = uint(data).map(|v| v as i64),
+                            PIXEL_HEIGHT => height = uint(data).map(|v| v as i64),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let Some(kind) = kind else {
+            continue;
+        };
+        // tracks without a language are english, as the spec has it.
+        let language = language
+            .or(bcp47)
+            .unwrap_or_else(|| "eng".to_string())
+            .to_lowercase();
+        let track = Track {
+            number,
+            kind,
+            codec,
+            language: (language != "und").then_some(language),
+            name,
+        };
+        info.add_track(track, width, height);
+    }
+}
+
+/// common name of a matroska codec id, the id itself in lower case for others.
+fn codec_name(id: &str) -> String {
+    let name = match id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP9" => "vp9",
+        "V_VP8" => "vp8",
+        "V_MPEG4/ISO/ASP" => "mpeg4",
+        "V_MPEG2" => "mpeg2",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_FLAC" => "flac",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_MPEG/L3" => "mp3",
+        "A_TRUEHD" => "truehd",
+        "S_TEXT/UTF8" => "srt",
+        "S_TEXT/ASS" => "ass",
+        "S_TEXT/SSA" => "ssa",
+        "S_TEXT/WEBVTT" => "webvtt",
+        "S_HDMV/PGS" => "pgs",
+        "S_VOBSUB" => "vobsub",
+        id if id.starts_with("A_AAC") => "aac",
+        id if id.starts_with("A_DTS") => "dts",
+        id => return id.to_lowercase(),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::uuid_v4;
+
+    /// an element with an 8 byte size, as some muxers write them.
+    fn element(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut e: Vec<u8> = id
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        e.push(0x01);
+        e.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+        e.extend_from_slice(data);
+        e
+    }
+
+    fn track(number: u8, kind: u8, codec: &str, language: Option<&str>, video: &[u8]) -> Vec<u8> {
+        let mut entry = [
+            element(TRACK_NUMBER as u32, &[number]),
+            element(TRACK_TYPE as u32, &[kind]),
+            element(CODEC_ID as u32, codec.as_bytes()),
+            video.to_vec(),
+        ]
+        .concat();
+        if let Some(l) = language {
+            entry.extend(element(LANGUAGE as u32, l.as_bytes()));
+        }
+        element(TRACK_ENTRY as u32, &entry)
+    }
+
+    fn sample_mkv() -> Vec<u8> {
+        let video = element(
+            VIDEO as u32,
+            &[
+                element(PIXEL_WIDTH as u32, &[0x0f, 0x00]),
+                element(PIXEL_HEIGHT as u32, &[0x08, 0x70]),
+            ]
+            .concat(),
+        );
+        let info = [
+            element(TIMESTAMP_SCALE as u32, &[0x0f, 0x42, 0x40]),
+            element(DURATION as u32, &1_425_000.0f64.to_be_bytes()),
+            element(TITLE as u32, "迷宫".as_bytes()),
+        ]
+        .concat();
+        let tracks = [
+            track(1, 1, "V_MPEGH/ISO/HEVC", Some("und"), &video),
+            track(2, 2, "A_AAC/MPEG4/LC", Some("jpn"), &[]),
+            track(3, 17, "S_TEXT/ASS", Some("chi"), &[]),
+            track(4, 17, "S_TEXT/UTF8", None, &[]),
+        ]
+        .concat();
+        // a live style segment of unknown size.
+        let mut segment = vec![
+            0x18, 0x53, 0x80, 0x67, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        segment.extend(element(INFO as u32, &info));
+        segment.extend(element(TRACKS as u32, &tracks));
+        segment.extend(element(CLUSTER as u32, &[0; 256]));
+
+        [
+            element(EBML as u32, &element(DOC_TYPE as u32, b"matroska")),
+            segment,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("{}.mkv", uuid_v4()));
+        fs::write(&path, sample_mkv()).unwrap();
+        let info = read(&path).unwrap().unwrap();
+        fs::write(&path, b"not a matroska file").unwrap();
+        assert!(read(&path).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(info.duration_ms, Some(1_425_000));
+        assert_eq!(info.title.as_deref(), Some("迷宫"));
+        assert_eq!((info.width, info.height), (Some(3840), Some(2160)));
+        assert_eq!(info.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        let tracks: Vec<_> = info
+            .tracks
+            .iter()
+            .map(|t| (t.number, t.kind, t.language.as_deref()))
+            .collect();
+        assert_eq!(
+            tracks,
+            vec![
+                (1, TrackKind::Video, None),
+                (2, TrackKind::Audio, Some("jpn")),
+                (3, TrackKind::Subtitle, Some("chi")),
+                (4, TrackKind::Subtitle, Some("eng")),
+            ]
+        );
+    }
+}
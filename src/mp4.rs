@@ -0,0 +1,402 @@
+use crate::id3;
+use crate::media_info::{MediaInfo, Tags, Track, TrackKind};
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// extensions of iso base media files: mp4, quicktime and their kin, itunes audio included.
+pub const EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "3gp", "m4a", "m4b"];
+
+/// the `moov` box is read into memory whole. its sample tables grow with the length of the
+/// film, but even hours of it stay well under this; a size past it is a broken box header.
+const MAX_MOOV_SIZE: u64 = 64 << 20;
+
+/// seconds from 1904-01-01, where mp4 times count from, to 1970-01-01.
+const EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// duration, size, codecs and creation time out of the `moov` box of `path`, `None` when it
+/// has none.
+pub fn read(path: &Path) -> io::Result<Option<MediaInfo>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    Ok(find_moov(&mut file, len)?.map(|moov| parse_moov(&moov)))
+}
+
+/// body of the top level `moov` box, skipping over `mdat` and whatever else comes before it.
+fn find_moov(r: &mut (impl Read + Seek), len: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut pos: u64 = 0;
+    while pos.checked_add(8).is_some_and(|end| end <= len) {
+        r.seek(SeekFrom::Start(pos))?;
+        let mut header = [0; 16];
+        r.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            r.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+
+        if &header[4..8] == b"moov" {
+            if size > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let mut body = vec![0; (size - header_len) as usize];
+            r.read_exact(&mut body)?;
+            return Ok(Some(body));
+        }
+        match pos.checked_add(size) {
+            Some(end) if end <= len => pos = end,
+            // a box that runs past the end of the file, there is nothing after it.
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(None)
+}
+
+/// the boxes in a buffer, as type and body.
+struct Boxes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.0;
+        let size = u32_at(buf, 0)? as u64;
+        let kind: [u8; 4] = buf.get(4..8)?.try_into().ok()?;
+        let (start, size) = match size {
+            0 => (8, buf.len() as u64),
+            1 => (16, u64_at(buf, 8)?),
+            _ => (8, size),
+        };
+        let end = usize::try_from(size).ok().filter(|&e| e >= start)?;
+        let body = buf.get(start..end)?;
+        self.0 = &buf[end..];
+        Some((kind, body))
+    }
+}
+
+/// body of the first box of type `kind` in `buf`.
+fn child<'a>(buf: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    Boxes(buf).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+fn u16_at(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(buf.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(buf: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(buf.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// common name of the codec a sample entry type stands for, the type itself for others.
+fn codec_name(kind: &[u8]) -> String {
+    let name = match kind {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"av01" => "av1",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"mp4a" => "aac",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"Opus" => "opus",
+        b"fLaC" => "flac",
+        b".mp3" => "mp3",
+        b"tx3g" => "tx3g",
+        _ => return String::from_utf8_lossy(kind).trim().to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// iso 639-2 code packed into 15 bits of a media header, `None` for `und`.
+fn language(packed: u16) -> Option<String> {
+    // smaller values are old quicktime language numbers.
+    if packed < 0x400 {
+        return None;
+    }
+    let code: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (((packed >> shift) & 0x1f) as u8 + 0x60) as char)
+        .collect();
+    (code != "und" && code.chars().all(|c| c.is_ascii_lowercase())).then_some(code)
+}
+
+fn parse_moov(moov: &[u8]) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    for (kind, body) in Boxes(moov) {
+        match &kind {
+            b"mvhd" => {
+                parse_mvhd(body, &mut info);
+            }
+            b"trak" => {
+                parse_trak(body, &mut info);
+            }
+            b"udta" => {
+                info.tags = parse_udta(body).filter(|t| !t.is_empty());
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+/// itunes style tags from `udta/meta/ilst`, each item holding its value in a `data` box.
+fn parse_udta(udta: &[u8]) -> Option<Tags> {
+    let meta = child(udta, b"meta")?;
+    // a full box in mp4, a plain one in quicktime.
+    let meta = if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..)?
+    };
+
+    let mut tags = Tags::default();
+    for (kind, item) in Boxes(child(meta, b"ilst")?) {
+        // type and locale come before the value.
+        let Some(value) = child(item, b"data").and_then(|d| d.get(8..)) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(value);
+        match &kind {
+            b"\xa9nam" => tags.set("TITLE", &text),
+            b"\xa9ART" => tags.set("ARTIST", &text),
+            b"\xa9alb" => tags.set("ALBUM", &text),
+            b"aART" => tags.set("ALBUMARTIST", &text),
+            b"\xa9gen" => tags.set("GENRE", &text),
+            b"\xa9day" => tags.set("DATE", &text),
+            b"gnre" => {
+                // id3v1 genre plus one.
+                let n = u16_at(value, 0).unwrap_or_default() as usize;
+                tags.genre = n.checked_sub(1).and_then(id3::genre);
+            }
+            b"trkn" => {
+                tags.track = u16_at(value, 2).filter(|&n| n > 0).map(i64::from);
+            }
+            _ => {}
+        }
+    }
+    Some(tags)
+}
+
+/// movie header: creation time, time scale and duration.
+fn parse_mvhd(body: &[u8], info: &mut MediaInfo) -> Option<()> {
+    let (created, timescale, duration) = if body.first()? == &1 {
+        (u64_at(body, 4)?, u32_at(body, 20)?, u64_at(body, 24)?)
+    } else {
+        let duration = u32_at(body, 16)?;
+        // all ones is an unknown duration.
+        let duration = if duration == u32::MAX {
+            u64::MAX
+        } else {
+            duration as u64
+        };
+        (u32_at(body, 4)? as u64, u32_at(body, 12)?, duration)
+    };
+
+    if timescale > 0 && duration != u64::MAX {
+        info.duration_ms = i64::try_from(duration as u128 * 1000 / timescale as u128).ok();
+    }
+    if created > 0 {
+        info.created = i64::try_from(created)
+            .ok()
+            .and_then(|t| t.checked_sub(EPOCH_OFFSET))
+            .and_then(|t| Utc.timestamp_opt(t, 0).single());
+    }
+    Some(())
+}
+
+/// a track: its handler tells video from sound, the sample description names the codec.
+fn parse_trak(trak: &[u8], info: &mut MediaInfo) -> Option<()> {
+    let tkhd = child(trak, b"tkhd")?;
+    let mdia = child(trak, b"mdia")?;
+    let kind = match child(mdia, b"hdlr")?.get(8..12)? {
+        b"vide" => TrackKind::Video,
+        b"soun" => TrackKind::Audio,
+        b"sbtl" | b"subt" | b"text" | b"clcp" => TrackKind::Subtitle,
+        _ => return None,
+    };
+    let stsd = child(child(child(mdia, b"minf")?, b"stbl")?, b"stsd")?;
+    // version and flags, entry count, then the first sample entry.
+    let (codec, entry) = Boxes(stsd.get(8..)?).next()?;
+
+    let v1 = tkhd.first()? == &1;
+    let number = u32_at(tkhd, if v1 { 20 } else { 12 })?;
+    let mdhd = child(mdia, b"mdhd");
+    let packed = mdhd.and_then(|m| u16_at(m, if m.first() == Some(&1) { 32 } else { 20 }));
+
+    let (mut width, mut height) = (None, None);
+    if kind == TrackKind::Video {
+        // display size from the track header, in 16.16 fixed point.
+        let at = if v1 { 36 } else { 24 };
+        let (w, h) = (u32_at(tkhd, at + 52)? >> 16, u32_at(tkhd, at + 56)? >> 16);
+        let (w, h) = if w > 0 && h > 0 {
+            (w, h)
+        } else {
+            // coded size from the visual sample entry.
+            (u16_at(entry, 24)? as u32, u16_at(entry, 26)? as u32)
+        };
+        (width, height) = (Some(w as i64), Some(h as i64));
+    }
+
+    let track = Track {
+        number: number as i64,
+        kind,
+        codec: Some(codec_name(&codec)),
+        language: packed.and_then(language),
+        name: None,
+    };
+    info.add_track(track, width, height);
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::uuid_v4;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut b = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(kind);
+        b.extend_from_slice(body);
+        b
+    }
+
+    fn trak(
+        number: u32,
+        handler: &[u8; 4],
+        codec: &[u8; 4],
+        size: (u32, u32),
+        lang: u16,
+    ) -> Vec<u8> {
+        let (width, height) = size;
+        let mut tkhd = vec![0; 84];
+        tkhd[12..16].copy_from_slice(&number.to_be_bytes());
+        tkhd[76..80].copy_from_slice(&(width << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(height << 16).to_be_bytes());
+        let mut hdlr = vec![0; 24];
+        hdlr[8..12].copy_from_slice(handler);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(codec, &[0; 70]));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let minf = mp4_box(b"minf", &stbl);
+        let mut mdhd = vec![0; 24];
+        mdhd[20..22].copy_from_slice(&lang.to_be_bytes());
+        let mdia = mp4_box(
+            b"mdia",
+            &[mp4_box(b"mdhd", &mdhd), mp4_box(b"hdlr", &hdlr), minf].concat(),
+        );
+        mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mdia].concat())
+    }
+
+    fn ilst_item(kind: &[u8; 4], value: &[u8]) -> Vec<u8> {
+        let data = [&[0, 0, 0, 1, 0, 0, 0, 0], value].concat();
+        mp4_box(kind, &mp4_box(b"data", &data))
+    }
+
+    fn udta() -> Vec<u8> {
+        let ilst = [
+            ilst_item(b"\xa9nam", "千与千寻".as_bytes()),
+            ilst_item(b"\xa9day", b"2001-07-20T00:00:00Z"),
+            ilst_item(b"trkn", &[0, 0, 0, 2, 0, 12, 0, 0]),
+            ilst_item(b"gnre", &[0, 25]),
+        ]
+        .concat();
+        let meta = [
+            [0; 4].to_vec(),
+            mp4_box(b"hdlr", &[0; 25]),
+            mp4_box(b"ilst", &ilst),
+        ]
+        .concat();
+        mp4_box(b"udta", &mp4_box(b"meta", &meta))
+    }
+
+    /// a file with the `moov` after the media data, as cameras write them.
+    fn sample_mp4() -> Vec<u8> {
+        let mut mvhd = vec![0; 100];
+        // 2023-03-01 00:00:00 utc.
+        mvhd[4..8].copy_from_slice(&((1_677_628_800 + EPOCH_OFFSET) as u32).to_be_bytes());
+        mvhd[12..16].copy_from_slice(&600u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&(600u32 * 5400 + 300).to_be_bytes());
+        let moov = [
+            mp4_box(b"mvhd", &mvhd),
+            trak(1, b"vide", b"avc1", (1920, 1080), 0x55c4),
+            // `jpn` and `eng`.
+            trak(2, b"soun", b"mp4a", (0, 0), 0x2a0e),
+            trak(3, b"soun", b"ac-3", (0, 0), 0x15c7),
+            udta(),
+        ]
+        .concat();
+        [
+            mp4_box(b"ftyp", b"isom\0\0\x02\0isomavc1"),
+            mp4_box(b"mdat", &[0; 4096]),
+            mp4_box(b"moov", &moov),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("{}.mp4", uuid_v4()));
+        fs::write(&path, sample_mp4()).unwrap();
+        let info = read(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(info.duration_ms, Some(5_400_500));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        let languages: Vec<_> = info.tracks.iter().map(|t| t.language.as_deref()).collect();
+        assert_eq!(languages, vec![None, Some("jpn"), Some("eng")]);
+        assert_eq!(info.tracks[2].codec.as_deref(), Some("ac3"));
+        assert_eq!(info.tracks[2].number, 3);
+        assert_eq!(
+            info.created,
+            Utc.with_ymd_and_hms(2023, 3, 1, 0, 0, 0).single()
+        );
+
+        assert_eq!(
+            info.tags,
+            Some(Tags {
+                title: Some("千与千寻".to_string()),
+                genre: Some("Soundtrack".to_string()),
+                track: Some(2),
+                year: Some(2001),
+                ..Default::default()
+            })
+        );
+
+        let mut junk = std::io::Cursor::new(vec![0xff; 64]);
+        assert!(find_moov(&mut junk, 64).unwrap().is_none());
+
+        // a creation time too far out for a date is none.
+        let mut mvhd = vec![0; 112];
+        mvhd[0] = 1;
+        mvhd[4..12].copy_from_slice(&u64::MAX.to_be_bytes());
+        mvhd[20..24].copy_from_slice(&600u32.to_be_bytes());
+        let mut info = MediaInfo::default();
+        parse_mvhd(&mvhd, &mut info).unwrap();
+        assert_eq!(info.created, None);
+
+        // a 64 bit size that would take the position past the end, or around it.
+        for size in [u64::MAX - 4, 1 << 40] {
+            let mut header = [0, 0, 0, 1, b'f', b'r', b'e', b'e'].to_vec();
+            header.extend_from_slice(&size.to_be_bytes());
+            header.extend_from_slice(&mp4_box(b"moov", &[]));
+            let len = header.len() as u64;
+            let mut junk = std::io::Cursor::new(header);
+            assert!(find_moov(&mut junk, len).unwrap().is_none());
+        }
+    }
+}
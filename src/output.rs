@@ -0,0 +1,327 @@
+use crate::file::File;
+use crate::volume::Volume;
+use clap::ValueEnum;
+use eyre::Result;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// one name per line, see `--show-path` and `--long`.
+    #[default]
+    Plain,
+    /// a single json array.
+    Json,
+    /// one json object per line.
+    Ndjson,
+    Csv,
+    /// tab separated, tabs, newlines and backslashes in values are escaped as `\t`, `\n`, `\\`.
+    Tsv,
+    /// full paths separated by nul, for `xargs -0`.
+    Paths0,
+}
+
+/// a field of `File`, named as it is serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum Column {
+    Id,
+    FullPath,
+    FileName,
+    Hostname,
+    Dir,
+    Timestamp,
+    Size,
+    Modified,
+    Created,
+    Device,
+    Inode,
+    VolumeId,
+    RelPath,
+    LastSeen,
+    Hash,
+}
+
+impl Column {
+    pub fn name(&self) -> String {
+        self.to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// columns of csv and tsv output when none are asked for.
+const DEFAULT_COLUMNS: &[Column] = &[
+    Column::FullPath,
+    Column::FileName,
+    Column::Hostname,
+    Column::Dir,
+    Column::Size,
+    Column::Modified,
+    Column::Created,
+    Column::Timestamp,
+];
+
+/// writes found files one by one in the chosen format.
+pub struct Printer<W: Write> {
+    out: W,
+    format: Format,
+    columns: Vec<Column>,
+    show_path: bool,
+    long: bool,
+    count: usize,
+    /// known volumes by id, and whether they are mounted here once asked.
+    volumes: HashMap<String, (Volume, Option<bool>)>,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, format: Format, columns: Vec<Column>) -> Self {
+        Self {
+            out,
+            format,
+            columns,
+            show_path: false,
+            long: false,
+            count: 0,
+            volumes: HashMap::new(),
+        }
+    }
+
+    /// volumes files may be on, so `Format::Plain` can tell which ones are offline.
+    pub fn volumes(mut self, volumes: Vec<Volume>) -> Self {
+        self.volumes = volumes
+            .into_iter()
+            .map(|v| (v.id.clone(), (v, None)))
+            .collect();
+        self
+    }
+
+    /// ` on disk X (offline)` when the volume of `f` is not mounted here.
+    fn offline(&mut self, f: &File) -> String {
+        let Some((volume, mounted)) = f.volume_id.as_ref().and_then(|id| self.volumes.get_mut(id))
+        else {
+            return String::new();
+        };
+
+        if *mounted.get_or_insert_with(|| volume.is_mounted()) {
+            String::new()
+        } else {
+            format!(" on disk {} (offline)", volume.name())
+        }
+    }
+
+    /// how `Format::Plain` shows a file.
+    pub fn plain(mut self, show_path: bool, long: bool) -> Self {
+        self.show_path = show_path;
+        self.long = long;
+        self
+    }
+
+    fn table_columns(&self) -> &[Column] {
+        if self.columns.is_empty() {
+            DEFAULT_COLUMNS
+        } else {
+            &self.columns
+        }
+    }
+
+    fn header(&mut self) -> Result<()> {
+        match self.format {
+            Format::Json => write!(self.out, "[")?,
+            Format::Csv | Format::Tsv => {
+                let names: Vec<String> = self.table_columns().iter().map(|c| c.name()).collect();
+                self.write_row(&names)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, fields: &[String]) -> Result<()> {
+        let (separator, escape): (&str, fn(&str) -> String) = match self.format {
+            Format::Tsv => ("\t", escape_tsv),
+            _ => (",", escape_csv),
+        };
+        let line: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+        writeln!(self.out, "{}", line.join(separator))?;
+
+        Ok(())
+    }
+
+    /// the file as a json object, restricted to the asked for columns if any.
+    fn object(&self, f: &File) -> Result<Value> {
+        let value = serde_json::to_value(f)?;
+        if self.columns.is_empty() {
+            return Ok(value);
+        }
+
+        let mut object = Map::new();
+        for c in &self.columns {
+            let name = c.name();
+            let v = value.get(&name).cloned().unwrap_or(Value::Null);
+            object.insert(name, v);
+        }
+        Ok(Value::Object(object))
+    }
+
+    pub fn print(&mut self, f: &File) -> Result<()> {
+        if self.count == 0 {
+            self.header()?;
+        }
+        self.count += 1;
+
+        match self.format {
+            Format::Plain => {
+                let offline = self.offline(f);
+                let name = if !self.show_path {
+                    format!("{}{offline}", f.file_name)
+                } else {
+                    format!("{}:({}){offline}", f.file_name, f.full_path)
+                };
+
+                if self.long {
+                    let modified = f
+                        .modified
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_else(|| "-".to_string());
+                    writeln!(
+                        self.out,
+                        "{:>14} {} {:>10} {}",
+                        f.size, modified, f.inode, name
+                    )?;
+                } else {
+                    writeln!(self.out, "{name}")?;
+                }
+            }
+            Format::Json => {
+                if self.count > 1 {
+                    write!(self.out, ",")?;
+                }
+                let object = self.object(f)?;
+                write!(self.out, "\n  {}", serde_json::to_string(&object)?)?;
+            }
+            Format::Ndjson => {
+                let object = self.object(f)?;
+                writeln!(self.out, "{}", serde_json::to_string(&object)?)?;
+            }
+            Format::Csv | Format::Tsv => {
+                let value = serde_json::to_value(f)?;
+                let fields: Vec<String> = self
+                    .table_columns()
+                    .iter()
+                    .map(|c| match value.get(c.name()) {
+                        None | Some(Value::Null) => String::new(),
+                        Some(Value::String(s)) => s.clone(),
+                        Some(v) => v.to_string(),
+                    })
+                    .collect();
+                self.write_row(&fields)?;
+            }
+            Format::Paths0 => write!(self.out, "{}\0", f.full_path)?,
+        }
+
+        Ok(())
+    }
+
+    /// close what `print` opened and flush, also prints the header when nothing was found.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.header()?;
+        }
+        if self.format == Format::Json {
+            writeln!(self.out, "{}]", if self.count == 0 { "" } else { "\n" })?;
+        }
+        self.out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn escape_tsv(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn print(format: Format, columns: Vec<Column>) -> String {
+        let mut f = File::new(
+            "/Volumes/Media/Tom's \"best\", vol.1.mp4".to_string(),
+            "Tom's \"best\", vol.1.mp4".to_string(),
+            false,
+            Some("nas:liwei".to_string()),
+        );
+        f.size = 42;
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, format, columns);
+        printer.print(&f).unwrap();
+        printer.print(&f).unwrap();
+        printer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_printer() {
+        let csv = print(Format::Csv, vec![Column::FileName, Column::Size]);
+        assert_eq!(
+            csv,
+            "file_name,size\n\"Tom's \"\"best\"\", vol.1.mp4\",42\n\"Tom's \"\"best\"\", vol.1.mp4\",42\n"
+        );
+
+        let ndjson = print(Format::Ndjson, vec![Column::Size, Column::Hostname]);
+        assert_eq!(
+            ndjson,
+            "{\"size\":42,\"hostname\":\"nas:liwei\"}\n{\"size\":42,\"hostname\":\"nas:liwei\"}\n"
+        );
+
+        let json: Value = serde_json::from_str(&print(Format::Json, vec![])).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(
+            json[0]["full_path"],
+            "/Volumes/Media/Tom's \"best\", vol.1.mp4"
+        );
+
+        let mut out = vec![];
+        let mut f = File::new(
+            "/Volumes/Media/a.mkv".to_string(),
+            "a.mkv".to_string(),
+            false,
+            None,
+        );
+        f.volume_id = Some("1234-5678".to_string());
+        let mut volume = Volume::detect(std::path::Path::new("Cargo.toml")).unwrap();
+        volume.id = "1234-5678".to_string();
+        volume.label = Some("Media".to_string());
+        volume.mount_point = "/Volumes/Media".to_string();
+        let mut printer = Printer::new(&mut out, Format::Plain, vec![]).volumes(vec![volume]);
+        printer.print(&f).unwrap();
+        printer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a.mkv on disk Media (offline)\n"
+        );
+
+        let paths = print(Format::Paths0, vec![]);
+        assert_eq!(paths.split('\0').count(), 3);
+
+        let mut out = vec![];
+        Printer::new(&mut out, Format::Json, vec![])
+            .finish()
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[]\n");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::file::File;
+use crate::util::format_size;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// how often the line is redrawn on a terminal.
+const TTY_INTERVAL: Duration = Duration::from_millis(100);
+/// how often a line is logged when stdout is no terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// characters of the current directory shown, its end is the part that tells most.
+const DIR_WIDTH: usize = 60;
+
+/// how far a walk got: redrawn in place on a terminal, logged every so often otherwise.
+pub struct Progress {
+    tty: bool,
+    started: Instant,
+    shown: Instant,
+    /// entries of the last complete scan of the same root, for an eta.
+    expected: Option<u64>,
+    /// entries counted by the scan this one resumes.
+    resumed: u64,
+    files: u64,
+    dirs: u64,
+    bytes: i64,
+    dir: String,
+}
+
+/// whether progress is drawn in place rather than logged.
+pub fn on_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+impl Progress {
+    pub fn new(expected: Option<u64>, resumed: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            tty: on_terminal(),
+            started: now,
+            shown: now,
+            expected,
+            resumed,
+            files: 0,
+            dirs: 0,
+            bytes: 0,
+            dir: String::new(),
+        }
+    }
+
+    /// count `f` in, and show where the walk is when it is time to.
+    pub fn update(&mut self, f: &File) {
+        if f.dir {
+            self.dirs += 1;
+            self.dir.clone_from(&f.full_path);
+        } else {
+            self.files += 1;
+            self.bytes += f.size;
+            if let Some(parent) = Path::new(&f.full_path).parent() {
+                if parent.as_os_str() != self.dir.as_str() {
+                    self.dir = parent.display().to_string();
+                }
+            }
+        }
+
+        let interval = if self.tty { TTY_INTERVAL } else { LOG_INTERVAL };
+        if self.shown.elapsed() < interval {
+            return;
+        }
+        self.shown = Instant::now();
+        let line = self.line(self.started.elapsed());
+        if self.tty {
+            let mut out = io::stdout().lock();
+            let _ = write!(out, "\r\x1b[2K{line}");
+            let _ = out.flush();
+        } else {
+            info!("{line}");
+        }
+    }
+
+    /// clear the line, for the summary to take its place.
+    pub fn finish(&self) {
+        if self.tty {
+            let mut out = io::stdout().lock();
+            let _ = write!(out, "\r\x1b[2K");
+            let _ = out.flush();
+        }
+    }
+
+    fn line(&self, elapsed: Duration) -> String {
+        let entries = self.files + self.dirs;
+        let rate = entries as f64 / elapsed.as_secs_f64().max(0.001);
+        let mut s = format!(
+            "{} files, {} dirs, {}, {:.0}/s",
+            self.files,
+            self.dirs,
+            format_size(self.bytes),
+            rate
+        );
+        if let Some(expected) = self.expected {
+            let left = expected.saturating_sub(self.resumed + entries);
+            if left > 0 && rate > 0.0 {
+                s.push_str(&format!(", eta {}", format_eta(left as f64 / rate)));
+            }
+        }
+        s.push_str(&format!(", {}", tail(&self.dir, DIR_WIDTH)));
+        s
+    }
+}
+
+/// `1h02m`, `3m20s` or `45s`.
+fn format_eta(seconds: f64) -> String {
+    let s = seconds.ceil() as u64;
+    match s {
+        0..=59 => format!("{s}s"),
+        60..=3599 => format!("{}m{:02}s", s / 60, s % 60),
+        _ => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// the last `width` characters of `s`, marked as cut when there were more.
+fn tail(s: &str, width: usize) -> String {
+    let count = s.chars().count();
+    if count <= width {
+        return s.to_string();
+    }
+    let cut: String = s.chars().skip(count - width + 1).collect();
+    format!("…{cut}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::log_init;
+
+    #[test]
+    fn test_line() {
+        log_init();
+        let mut p = Progress::new(Some(3000), 500);
+        p.tty = false;
+        let mut dir = File::new("/data/电影".to_string(), "电影".to_string(), true, None);
+        p.update(&dir);
+        let mut f = File::new(
+            "/data/电影/a.mkv".to_string(),
+            "a.mkv".to_string(),
+            false,
+            None,
+        );
+        f.size = 1536;
+        for _ in 0..499 {
+            p.update(&f);
+        }
+        assert_eq!(
+            p.line(Duration::from_secs(10)),
+            "499 files, 1 dirs, 748.5 KiB, 50/s, eta 40s, /data/电影"
+        );
+
+        // nothing left to go is no eta.
+        p.expected = Some(900);
+        dir.full_path = format!("/data/{}", "長".repeat(80));
+        p.update(&dir);
+        let line = p.line(Duration::from_secs(10));
+        assert!(line.starts_with("499 files, 2 dirs, 748.5 KiB, 50/s, …長"));
+        assert_eq!(
+            line.chars().count(),
+            "499 files, 2 dirs, 748.5 KiB, 50/s, ".len() + 60
+        );
+
+        assert_eq!(format_eta(3725.0), "1h02m");
+        assert_eq!(format_eta(200.0), "3m20s");
+    }
+}
@@ -0,0 +1,90 @@
+use libsqlite3_sys::{
+    sqlite3_context, sqlite3_create_function_v2, sqlite3_get_auxdata, sqlite3_result_error,
+    sqlite3_result_int, sqlite3_set_auxdata, sqlite3_value, sqlite3_value_bytes,
+    sqlite3_value_text, SQLITE_DETERMINISTIC, SQLITE_OK, SQLITE_UTF8,
+};
+use regex::Regex;
+use sqlx::sqlite::SqliteConnection;
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_int};
+use std::{slice, str};
+
+/// give the connection `regexp(pattern, text)`, which sqlite calls for `text regexp pattern`,
+/// so a regex is matched, sorted and paged in sql like every other query.
+pub async fn register(conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    let mut handle = conn.lock_handle().await?;
+    // safety: the handle is locked out from the worker thread, and the name outlives the call.
+    let rc = unsafe {
+        sqlite3_create_function_v2(
+            handle.as_raw_handle().as_ptr(),
+            c"regexp".as_ptr(),
+            2,
+            SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+            std::ptr::null_mut(),
+            Some(regexp),
+            None,
+            None,
+            None,
+        )
+    };
+    if rc != SQLITE_OK {
+        return Err(sqlx::Error::Protocol(format!(
+            "could not register regexp, sqlite error {rc}."
+        )));
+    }
+
+    Ok(())
+}
+
+/// the text of `v`, none for null or what is not utf-8.
+unsafe fn text<'a>(v: *mut sqlite3_value) -> Option<&'a str> {
+    let p = sqlite3_value_text(v);
+    if p.is_null() {
+        return None;
+    }
+    let len = sqlite3_value_bytes(v) as usize;
+    str::from_utf8(slice::from_raw_parts(p, len)).ok()
+}
+
+unsafe extern "C" fn drop_regex(p: *mut c_void) {
+    drop(Box::from_raw(p as *mut Regex));
+}
+
+/// the pattern is the same for every row of a statement, it is compiled once and kept with it.
+unsafe extern "C" fn regexp(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let kept = sqlite3_get_auxdata(ctx, 0) as *const Regex;
+    let compiled;
+    let re = if kept.is_null() {
+        let Some(pattern) = text(args[0]) else {
+            return;
+        };
+        compiled = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                let message = e.to_string();
+                sqlite3_result_error(
+                    ctx,
+                    message.as_ptr() as *const c_char,
+                    message.len() as c_int,
+                );
+                return;
+            }
+        };
+        &compiled
+    } else {
+        &*kept
+    };
+
+    // a null result for a null text, like any other sql function.
+    let Some(s) = text(args[1]) else {
+        return;
+    };
+    sqlite3_result_int(ctx, re.is_match(s) as c_int);
+
+    if kept.is_null() {
+        // sqlite may drop it right away, so it is not used after this.
+        let boxed = Box::into_raw(Box::new(re.clone()));
+        sqlite3_set_auxdata(ctx, 0, boxed as *mut c_void, Some(drop_regex));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::util::{hostname, uuid_v4};
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// errors listed by `summary`, the rest are only counted.
+const MAX_LISTED: usize = 20;
+
+/// what kept a scan from a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Permission,
+    Io,
+    Database,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Permission => "permission",
+            Self::Io => "io",
+            Self::Database => "database",
+        }
+    }
+
+    /// the kind `as_str` gave `s`, io for anything else.
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "permission" => Self::Permission,
+            "database" => Self::Database,
+            _ => Self::Io,
+        }
+    }
+}
+
+/// a path a scan could not read or save, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub path: String,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn io(path: &Path, e: &io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::PermissionDenied => ErrorKind::Permission,
+            _ => ErrorKind::Io,
+        };
+        Self {
+            path: path.display().to_string(),
+            kind,
+            message: e.to_string(),
+        }
+    }
+
+    pub fn database(path: &str, e: &sqlx::Error) -> Self {
+        Self {
+            path: path.to_string(),
+            kind: ErrorKind::Database,
+            message: e.to_string(),
+        }
+    }
+}
+
+/// one scan of a root: when it ran, what it found and every path it failed on.
+#[derive(Debug)]
+pub struct ScanReport {
+    pub id: String,
+    pub root: String,
+    pub hostname: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// files and directories walked.
+    pub entries: u64,
+    pub new: u64,
+    /// known files whose size or modification time changed.
+    pub updated: u64,
+    pub removed: u64,
+    /// paths that failed, `errors` is empty when a report is listed rather than loaded whole.
+    pub errored: u64,
+    pub errors: Vec<ScanError>,
+    /// walked, hashed and read all there was, rather than stopped or still going.
+    pub complete: bool,
+}
+
+impl ScanReport {
+    pub fn new(root: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid_v4(),
+            root: root.to_string(),
+            hostname: hostname(),
+            started: now,
+            finished: now,
+            entries: 0,
+            new: 0,
+            updated: 0,
+            removed: 0,
+            errored: 0,
+            errors: vec![],
+            complete: false,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = Utc::now();
+        self.errored = self.errors.len() as u64;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        (self.finished - self.started).to_std().unwrap_or_default()
+    }
+
+    /// paths under which nothing can be taken as gone, they were not all walked.
+    pub fn failed_paths(&self) -> Vec<String> {
+        self.errors.iter().map(|e| e.path.clone()).collect()
+    }
+
+    /// a line of totals, then the first errors.
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "scanned {} entries under {} in {:.1}s: {} new, {} updated, {} removed",
+            self.entries,
+            self.root,
+            self.elapsed().as_secs_f64(),
+            self.new,
+            self.updated,
+            self.removed
+        );
+        if self.errored == 0 {
+            s.push_str(".\n");
+            return s;
+        }
+
+        let count = |kind| self.errors.iter().filter(|e| e.kind == kind).count();
+        let counts: Vec<_> = [ErrorKind::Permission, ErrorKind::Io, ErrorKind::Database]
+            .into_iter()
+            .map(|kind| (kind, count(kind)))
+            .filter(|&(_, n)| n > 0)
+            .map(|(kind, n)| format!("{n} {}", kind.as_str()))
+            .collect();
+        let _ = writeln!(s, ", {} failed ({}):", self.errored, counts.join(", "));
+        s.push_str(&self.errors(MAX_LISTED));
+        s
+    }
+
+    /// a line for each of the first `limit` errors, and one for how many more there are.
+    pub fn errors(&self, limit: usize) -> String {
+        let mut s = String::new();
+        for e in self.errors.iter().take(limit) {
+            let _ = writeln!(s, "  {:<10} {}: {}", e.kind.as_str(), e.path, e.message);
+        }
+        if self.errors.len() > limit {
+            let _ = writeln!(s, "  and {} more.", self.errors.len() - limit);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::log_init;
+
+    #[test]
+    fn test_summary() {
+        log_init();
+        let mut report = ScanReport::new("/data");
+        report.entries = 42;
+        report.new = 3;
+        report.removed = 1;
+        assert_eq!(
+            report.summary(),
+            "scanned 42 entries under /data in 0.0s: 3 new, 0 updated, 1 removed.\n"
+        );
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        report
+            .errors
+            .push(ScanError::io(Path::new("/data/private"), &denied));
+        for i in 0..MAX_LISTED {
+            let e = sqlx::Error::Protocol("disk full".to_string());
+            report
+                .errors
+                .push(ScanError::database(&format!("/data/{i}.mkv"), &e));
+        }
+        report.finish();
+        let summary = report.summary();
+        let lines: Vec<_> = summary.lines().collect();
+        assert_eq!(
+            lines[0],
+            "scanned 42 entries under /data in 0.0s: 3 new, 0 updated, 1 removed, 21 failed (1 permission, 20 database):"
+        );
+        assert_eq!(lines[1], "  permission /data/private: permission denied");
+        assert_eq!(
+            lines[2],
+            "  database   /data/0.mkv: encountered unexpected or invalid data: disk full"
+        );
+        assert_eq!(lines.last(), Some(&"  and 1 more."));
+        assert_eq!(report.failed_paths()[0], "/data/private");
+    }
+}
@@ -1,113 +1,144 @@
 use crate::database::Database;
+use crate::exclude::Excludes;
 use crate::file::File;
-use async_walkdir::{DirEntry, WalkDir};
-use clap::Subcommand;
-use eyre::Result;
-use futures::{stream, StreamExt};
-use std::io::ErrorKind;
-use std::sync::atomic::{AtomicU64, Ordering};
+use crate::hash::{full_hash, partial_hash, PARTIAL_SIZE};
+use crate::media::{Category, FileFilter};
+use crate::media_info;
+use crate::progress::{on_terminal, Progress};
+use crate::report::{ScanError, ScanReport};
+use crate::settings::Settings;
+use crate::util::hostname;
+use crate::volume::Volume;
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+use eyre::{eyre, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use time::Instant;
-use tracing::{debug, error};
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tracing::{debug, trace, warn};
 
-const CHANNEL_BUFFER_SIZE: usize = 10000;
-const MAX_CONCURRENT_JUMPERS: usize = 20000;
-const DEFAULT_VOLUMES_PATH: &str = "/Volumes";
-const EXCLUDE_MAC_VOLUMES_PATH: &str = "/Volumes/Macintosh";
+pub const DEFAULT_VOLUMES_PATH: &str = "/Volumes";
 
 #[derive(Debug, Subcommand)]
 pub enum ScanCommand {
     Scan {
         #[arg(long, short)]
         name: Option<String>,
+        #[command(flatten)]
+        args: ScanArgs,
+        /// hash files that share their size with another one, for `dupes`.
+        #[arg(long)]
+        hash: bool,
+        /// files saved per transaction, instead of the number in config.toml.
+        #[arg(long)]
+        batch_size: Option<usize>,
+        /// go on with the last scan of this root that was stopped, past the directories it
+        /// finished.
+        #[arg(long)]
+        resume: bool,
+        /// no progress and no summary, only errors.
+        #[arg(long, short)]
+        quiet: bool,
     },
 }
 
+/// which files `scan` and `watch` keep.
+#[derive(Debug, Args)]
+#[command(about = None, long_about = None)]
+pub struct ScanArgs {
+    /// media categories to record, instead of the ones in config.toml.
+    #[arg(long = "category", short = 'c', value_enum)]
+    categories: Vec<Category>,
+    /// file extensions to record, instead of the ones in config.toml.
+    #[arg(long = "ext", short = 'e')]
+    extensions: Vec<String>,
+    /// gitignore style pattern of paths to leave out, on top of the ones in config.toml.
+    #[arg(long = "exclude", short = 'x')]
+    excludes: Vec<String>,
+}
+
+impl ScanArgs {
+    /// the file types of the categories and extensions given, or the ones in config.toml when
+    /// there are none.
+    pub fn filter(&self, settings: &Settings) -> FileFilter {
+        if self.categories.is_empty() && self.extensions.is_empty() {
+            FileFilter::new(&settings.scan.categories, &settings.scan.extensions)
+        } else {
+            FileFilter::new(&self.categories, &self.extensions)
+        }
+    }
+
+    /// the exclude patterns of config.toml, then the ones given.
+    pub fn patterns(&self, settings: &Settings) -> Vec<String> {
+        [settings.scan.excludes.as_slice(), &self.excludes].concat()
+    }
+}
+
+/// how `scan` walks a tree and saves what it finds.
+#[derive(Clone)]
+pub struct ScanOptions {
+    pub filter: FileFilter,
+    pub excludes: Excludes,
+    /// see `hash_files`.
+    pub hash: bool,
+    /// files saved per transaction, the walk waits when the writer falls behind by this many.
+    pub batch_size: usize,
+    /// go on with the last unfinished scan of the root, see `scan`.
+    pub resume: bool,
+    /// show how the walk goes, see `Progress`.
+    pub progress: bool,
+    /// keep the scan in the history `scans` lists, with checkpoints to resume it from.
+    pub record: bool,
+}
+
 impl ScanCommand {
-    pub async fn run(self, db: &mut impl Database) -> Result<()> {
-        let start = Instant::now();
-        let total_files = Arc::new(AtomicU64::new(0));
-        let db = Arc::new(db);
+    /// quiet, or drawing progress on a terminal.
+    pub fn quiet_logs(&self) -> bool {
+        let Self::Scan { quiet, .. } = self;
+        *quiet || on_terminal()
+    }
 
+    pub async fn run(self, db: &mut impl Database, settings: &Settings) -> Result<()> {
         match self {
-            Self::Scan { name } => {
+            Self::Scan {
+                name,
+                args,
+                hash,
+                batch_size,
+                resume,
+                quiet,
+            } => {
                 if name.is_some() {
                     debug!("scan name:{name:?}");
                 }
 
-                let (tx, mut rx) = tokio::sync::mpsc::channel(CHANNEL_BUFFER_SIZE);
-
-                let total_files1 = Arc::clone(&total_files);
-                tokio::spawn(async move {
-                    let mut entries =
-                        WalkDir::new(name.unwrap_or(DEFAULT_VOLUMES_PATH.to_string()));
-                    loop {
-                        match entries.next().await {
-                            Some(Ok(entry)) => {
-                                if is_hidden(&entry) || is_sys(&entry) {
-                                    continue;
-                                }
-
-                                // just scan director or .mp4 or .mp3 file.
-                                if !entry.path().is_dir() && !is_need_scan(&entry) {
-                                    continue;
-                                }
-
-                                // debug!("file:{}", entry.path().display());
-                                let f = File::new(
-                                    entry.path().display().to_string(),
-                                    entry.file_name().to_string_lossy().to_string(),
-                                    entry.path().is_dir(),
-                                    None,
-                                );
-
-                                if let Err(e) = tx.send(f).await {
-                                    error!("send channel error:{}", e);
-                                }
-
-                                // db.save(&f).await?;
-                                total_files1.fetch_add(1, Ordering::Relaxed);
-                            }
-                            Some(Err(e)) => match e.kind() {
-                                ErrorKind::PermissionDenied => {
-                                    error!("continue error:{}", e);
-                                    continue;
-                                }
-                                other_error => {
-                                    error!("error:{}", other_error);
-                                    break;
-                                }
-                            },
-                            None => {
-                                debug!("None");
-                                break;
-                            }
-                        }
-                    }
-                });
-
-                // while let Some(f) = rx.recv().await {
-                //     debug!("got file:{}", f.file_name);
-                //     db.save(&f).await?;
-                // }
-
-                // 并发处理
-                let input_rx_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
-                input_rx_stream
-                    .for_each_concurrent(10000, |ff| {
-                        let db = db.clone();
-                        async move {
-                            debug!("got file:{}", ff.file_name);
-                            db.save(&ff).await;
-                        }
-                    })
-                    .await;
-
-                debug!(
-                    "scan total:{}, elapsed:{}",
-                    total_files.load(Ordering::Relaxed),
-                    start.elapsed()
-                );
+                let root = canonical(&name.unwrap_or(DEFAULT_VOLUMES_PATH.to_string()));
+                let opts = ScanOptions {
+                    filter: args.filter(settings),
+                    excludes: Excludes::new(&root, &args.patterns(settings))?,
+                    hash,
+                    batch_size: batch_size.unwrap_or(settings.scan.batch_size).max(1),
+                    resume,
+                    progress: !quiet,
+                    record: true,
+                };
+                let report = scan(db, &root, opts).await?;
+                if !quiet {
+                    print!("{}", report.summary());
+                }
+                if !report.complete {
+                    return Err(eyre!(
+                        "scan stopped, `scan --resume` goes on from where it was."
+                    ));
+                }
+                if report.errored > 0 {
+                    return Err(eyre!("{} paths could not be scanned.", report.errored));
+                }
             }
         }
 
@@ -115,26 +146,724 @@ impl ScanCommand {
     }
 }
 
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s.starts_with("."))
-        .unwrap_or(false)
+/// absolute and without symlinks, so paths line up with volume mount points.
+pub fn canonical(root: &str) -> String {
+    fs::canonicalize(root)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| root.to_string())
+}
+
+/// set on ctrl-c: the walk stops at the next entry, hashing and reading media info at the
+/// next file, and what was found so far is saved.
+#[derive(Debug, Clone, Default)]
+pub struct Stop(Arc<AtomicBool>);
+
+impl Stop {
+    /// a stop that is set by ctrl-c for as long as the returned task runs.
+    fn on_ctrl_c() -> (Self, JoinHandle<()>) {
+        let stop = Self::default();
+        let set = stop.clone();
+        let task = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                warn!("interrupted, saving what was found so far.");
+                set.set();
+            }
+        });
+        (stop, task)
+    }
+
+    fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// what the walk hands the writer.
+enum Walked {
+    /// a wanted file or a directory, with the volume it is the first file of.
+    File(Box<File>, Option<Volume>),
+    /// a directory that was walked, with everything under it, without errors.
+    Done(String),
+}
+
+/// walk `root` and bring the catalog in line with it: save what is there, remove what is gone,
+/// then hash and read media info of what is new. paths that fail are skipped and end up in the
+/// report, which is saved along with the files. on ctrl-c the report is saved as it is, and a
+/// later scan with `resume` goes on from the directories it finished.
+pub async fn scan(db: &mut impl Database, root: &str, opts: ScanOptions) -> Result<ScanReport> {
+    let (stop, ctrl_c) = Stop::on_ctrl_c();
+    let report = scan_until(db, root, opts, &stop).await;
+    ctrl_c.abort();
+    report
+}
+
+/// `scan` until `stop` is set.
+async fn scan_until(
+    db: &mut impl Database,
+    root: &str,
+    opts: ScanOptions,
+    stop: &Stop,
+) -> Result<ScanReport> {
+    let ScanOptions {
+        filter,
+        excludes,
+        hash,
+        batch_size,
+        resume,
+        progress,
+        record,
+    } = opts;
+
+    let unfinished = if resume {
+        db.unfinished_scan(root, &hostname()).await?
+    } else {
+        None
+    };
+    let (mut report, done) = match unfinished {
+        Some(r) => {
+            let done: HashSet<String> = db.checkpoints(&r.id).await?.into_iter().collect();
+            debug!("resume scan:{} past {} directories", r.id, done.len());
+            (r, done)
+        }
+        None => {
+            if resume {
+                warn!("no unfinished scan of {root} to resume, starting over.");
+            }
+            (ScanReport::new(root), HashSet::new())
+        }
+    };
+    report.errors.clear();
+    // the row checkpoints are saved against.
+    if record {
+        db.save_report(&report).await?;
+    }
+    // every file found by this scan, or the one it resumes, is seen after this.
+    let since = report.started;
+    let mut progress = if progress {
+        let last = db.scans(Some(&report.root), 10).await?;
+        let expected = last
+            .iter()
+            .find(|r| r.complete && r.id != report.id)
+            .map(|r| r.entries);
+        Some(Progress::new(expected, report.entries))
+    } else {
+        None
+    };
+
+    // the walk stays at most a batch ahead of the writer.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(batch_size);
+    let walk_root = PathBuf::from(root);
+    let walk_stop = stop.clone();
+    let walk_filter = filter.clone();
+    let walker = tokio::task::spawn_blocking(move || {
+        walk(&walk_root, &walk_filter, excludes, &done, &walk_stop, &tx)
+    });
+
+    // the one writer: files go in `batch_size` at a time, each batch in one transaction, and
+    // directories are checkpointed once all files up to them are saved.
+    let mut walked = vec![];
+    // the volume of the root is known even when the walk keeps nothing on it, so what is gone
+    // from it is still removed.
+    if let Some(v) = Volume::detect(Path::new(root)) {
+        save_volume(db, v, &mut walked, &mut report).await;
+    }
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut finished = vec![];
+    while let Some(w) = rx.recv().await {
+        let (f, volume) = match w {
+            Walked::File(f, volume) => (*f, volume),
+            Walked::Done(dir) => {
+                if record {
+                    finished.push(dir);
+                }
+                continue;
+            }
+        };
+        report.entries += 1;
+        if let Some(p) = &mut progress {
+            p.update(&f);
+        }
+        if let Some(v) = volume {
+            save_volume(db, v, &mut walked, &mut report).await;
+        }
+        batch.push(f);
+        if batch.len() >= batch_size {
+            flush(db, &mut batch, &mut finished, &mut report).await?;
+        }
+    }
+    flush(db, &mut batch, &mut finished, &mut report).await?;
+    if let Some(p) = &progress {
+        p.finish();
+    }
+    let (errors, walked_all) = walker.await?;
+    report.errors.extend(errors);
+
+    // only a walk that went all the way can tell which files are gone.
+    if walked_all {
+        let removed = db
+            .remove_stale(root, &walked, since, &report.failed_paths(), &filter)
+            .await?;
+        for p in &removed {
+            trace!("removed file:{p}");
+        }
+        report.removed += removed.len() as u64;
+    }
+
+    if hash && !stop.is_set() {
+        let errors = hash_files(db, root, since, stop).await?;
+        report.errors.extend(errors);
+    }
+    if !stop.is_set() {
+        let errors = read_media_info(db, root, since, stop).await?;
+        report.errors.extend(errors);
+    }
+
+    report.complete = !stop.is_set();
+    report.finish();
+    if record {
+        db.save_report(&report).await?;
+    }
+
+    Ok(report)
+}
+
+/// save a volume the walk is on and add it to `walked`, once.
+async fn save_volume(
+    db: &mut impl Database,
+    v: Volume,
+    walked: &mut Vec<Volume>,
+    report: &mut ScanReport,
+) {
+    if walked.iter().any(|w| w.id == v.id) {
+        return;
+    }
+    trace!("got volume:{} at {}", v.name(), v.mount_point);
+    match db.save_volume(&v).await {
+        Ok(()) => walked.push(v),
+        Err(e) => {
+            warn!("could not save volume at {}:{}", v.mount_point, e);
+            report.errors.push(ScanError::database(&v.mount_point, &e));
+        }
+    }
+}
+
+/// save `batch`, then checkpoint the directories in `finished` that none of it failed under.
+async fn flush(
+    db: &mut impl Database,
+    batch: &mut Vec<File>,
+    finished: &mut Vec<String>,
+    report: &mut ScanReport,
+) -> Result<()> {
+    let failed = report.errors.len();
+    save_batch(db, batch, report).await?;
+    batch.clear();
+
+    let failed = &report.errors[failed..];
+    finished.retain(|dir| {
+        let prefix = format!("{dir}/");
+        !failed.iter().any(|e| e.path.starts_with(&prefix))
+    });
+    if !finished.is_empty() {
+        report.finished = Utc::now();
+        db.save_checkpoints(report, finished).await?;
+        finished.clear();
+    }
+    Ok(())
+}
+
+/// save `batch` in one go, or file by file when that fails so one bad row costs only itself.
+/// it is the database that is broken when not a single file can be saved.
+async fn save_batch(db: &mut impl Database, batch: &[File], report: &mut ScanReport) -> Result<()> {
+    let e = match db.save_bulk(batch).await {
+        Ok(saved) => {
+            report.new += saved.new;
+            report.updated += saved.updated;
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+    warn!("could not save a batch of {} files:{}", batch.len(), e);
+
+    let mut errors = vec![];
+    for f in batch {
+        match db.save_bulk(std::slice::from_ref(f)).await {
+            Ok(saved) => {
+                report.new += saved.new;
+                report.updated += saved.updated;
+            }
+            Err(e) => errors.push(ScanError::database(&f.full_path, &e)),
+        }
+    }
+    if errors.len() == batch.len() {
+        return Err(e.into());
+    }
+    report.errors.extend(errors);
+    Ok(())
 }
 
-fn is_sys(entry: &DirEntry) -> bool {
-    entry
-        .path()
-        .to_str()
-        .map(|s| s.starts_with(EXCLUDE_MAC_VOLUMES_PATH))
-        .unwrap_or(false)
+/// a directory being walked.
+struct Frame {
+    dir: PathBuf,
+    entries: fs::ReadDir,
+    /// nothing under it failed so far.
+    clean: bool,
 }
 
-fn is_need_scan(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s.ends_with(".mp4") || s.ends_with(".mp3"))
-        .unwrap_or(false)
+/// walk `root` depth first, sending the directories and wanted files in it, and each directory
+/// once all of it is walked without errors. hidden and excluded directories, and those in
+/// `done`, are not read at all. returns the paths that could not be read, the walk goes on past
+/// them, and whether it went all the way rather than stopped.
+fn walk(
+    root: &Path,
+    filter: &FileFilter,
+    mut excludes: Excludes,
+    done: &HashSet<String>,
+    stop: &Stop,
+    tx: &Sender<Walked>,
+) -> (Vec<ScanError>, bool) {
+    let mut errors = vec![];
+    let mut stack = vec![];
+    // whatever is being walked did not all make it.
+    let fail = |stack: &mut Vec<Frame>, errors: &mut Vec<ScanError>, path: &Path, e: io::Error| {
+        warn!("could not read {}:{}", path.display(), e);
+        errors.push(ScanError::io(path, &e));
+        for frame in stack.iter_mut() {
+            frame.clean = false;
+        }
+    };
+    let open = |stack: &mut Vec<Frame>, errors: &mut Vec<ScanError>, dir: PathBuf| {
+        match fs::read_dir(&dir) {
+            Ok(entries) => stack.push(Frame {
+                dir,
+                entries,
+                clean: true,
+            }),
+            Err(e) => fail(stack, errors, &dir, e),
+        }
+    };
+
+    if !done.contains(&root.display().to_string()) {
+        open(&mut stack, &mut errors, root.to_path_buf());
+    }
+    let mut volumes = HashMap::new();
+    while let Some(frame) = stack.last_mut() {
+        if stop.is_set() {
+            return (errors, false);
+        }
+        let entry = match frame.entries.next() {
+            Some(Ok(entry)) => entry,
+            Some(Err(e)) => {
+                let dir = frame.dir.clone();
+                fail(&mut stack, &mut errors, &dir, e);
+                continue;
+            }
+            None => {
+                let frame = stack.pop().unwrap();
+                let dir = frame.dir.display().to_string();
+                if frame.clean && tx.blocking_send(Walked::Done(dir)).is_err() {
+                    return (errors, false);
+                }
+                continue;
+            }
+        };
+        let path = entry.path();
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(e) => {
+                fail(&mut stack, &mut errors, &path, e);
+                continue;
+            }
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || excludes.is_excluded(&path, meta.is_dir()) {
+            continue;
+        }
+
+        // just scan director or the wanted file types.
+        if !meta.is_dir() && !filter.matches(&name) {
+            continue;
+        }
+
+        let mut f =
+            File::new(path.display().to_string(), name, meta.is_dir(), None).with_metadata(&meta);
+
+        // volumes are looked up once per device, and sent along with the first file on them.
+        let mut volume = None;
+        if let Some(v) = volumes.entry(f.device).or_insert_with(|| {
+            volume = Volume::detect(&path);
+            volume.clone()
+        }) {
+            f = f.with_volume(v);
+        }
+
+        let dir = f.dir && !done.contains(&f.full_path);
+        // the writer is gone, and tells why itself.
+        if tx.blocking_send(Walked::File(Box::new(f), volume)).is_err() {
+            return (errors, false);
+        }
+        if dir {
+            open(&mut stack, &mut errors, path);
+        }
+    }
+    (errors, true)
+}
+
+/// `hash` of the file at `path`, none when it lies outside `root` and is gone or changed since
+/// it was catalogued. its own root may not be mounted now, that is no error of this scan.
+fn hash_if_there(
+    root: &str,
+    f: &File,
+    hash: fn(&Path) -> io::Result<String>,
+) -> io::Result<Option<String>> {
+    let path = Path::new(&f.full_path);
+    if !path.starts_with(root) && fs::metadata(path).map_or(true, |m| m.len() != f.size as u64) {
+        return Ok(None);
+    }
+    hash(path).map(Some)
+}
+
+/// hash the files that may have a copy, where the file or its copy is under `root` and seen
+/// since `since`: the ends of those that share their size with another file first, then all
+/// of those whose ends match too. returns the files that could not be read.
+async fn hash_files(
+    db: &mut impl Database,
+    root: &str,
+    since: DateTime<Utc>,
+    stop: &Stop,
+) -> Result<Vec<ScanError>> {
+    let mut errors = vec![];
+    for mut f in db.partial_hash_candidates(root, since).await? {
+        if stop.is_set() {
+            return Ok(errors);
+        }
+        let (root, file) = (root.to_string(), f.clone());
+        match tokio::task::spawn_blocking(move || hash_if_there(&root, &file, partial_hash)).await?
+        {
+            Ok(None) => debug!("not hashing what is gone:{}", f.full_path),
+            Ok(Some(h)) => {
+                // the ends of a small file are all of it.
+                if f.size as u64 <= 2 * PARTIAL_SIZE {
+                    f.hash = Some(h.clone());
+                }
+                f.partial_hash = Some(h);
+                db.save_hash(&f).await?;
+            }
+            Err(e) => {
+                warn!("could not hash {}:{}", f.full_path, e);
+                errors.push(ScanError::io(Path::new(&f.full_path), &e));
+            }
+        }
+    }
+
+    for mut f in db.full_hash_candidates(root, since).await? {
+        if stop.is_set() {
+            break;
+        }
+        trace!("hashing file:{}", f.full_path);
+        let (root, file) = (root.to_string(), f.clone());
+        match tokio::task::spawn_blocking(move || hash_if_there(&root, &file, full_hash)).await? {
+            Ok(None) => debug!("not hashing what is gone:{}", f.full_path),
+            Ok(Some(h)) => {
+                f.hash = Some(h);
+                db.save_hash(&f).await?;
+            }
+            Err(e) => {
+                warn!("could not hash {}:{}", f.full_path, e);
+                errors.push(ScanError::io(Path::new(&f.full_path), &e));
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+/// media info of the videos under `root` seen since `since` that were not read before, or
+/// changed since. returns the files that could not be read.
+pub async fn read_media_info(
+    db: &mut impl Database,
+    root: &str,
+    since: DateTime<Utc>,
+    stop: &Stop,
+) -> Result<Vec<ScanError>> {
+    let mut errors = vec![];
+    for f in db.media_info_candidates(root, since).await? {
+        if stop.is_set() {
+            break;
+        }
+        let path = PathBuf::from(&f.full_path);
+        match tokio::task::spawn_blocking(move || media_info::read(&path)).await? {
+            Ok(mut info) => {
+                trace!("media info of {}:{:?}", f.full_path, info);
+                info.file_id = f.id;
+                db.save_media_info(&info).await?;
+            }
+            Err(e) => {
+                warn!("could not read media info of {}:{}", f.full_path, e);
+                // a file that is not what its name says is no failure of the scan.
+                if !matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof) {
+                    errors.push(ScanError::io(Path::new(&f.full_path), &e));
+                }
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Sqlite;
+    use crate::log::log_init;
+    use crate::search::SearchQuery;
+    use crate::util::uuid_v4;
+    use futures::TryStreamExt;
+    use std::time::Instant;
+
+    /// `dirs` directories of `files` empty text files each under `root`, not videos, so a scan
+    /// of them is all walking and writing with no media info to read.
+    fn make_tree(root: &Path, dirs: usize, files: usize) {
+        for d in 0..dirs {
+            let dir = root.join(format!("dir{d:04}"));
+            fs::create_dir_all(&dir).unwrap();
+            for f in 0..files {
+                fs::File::create(dir.join(format!("file{f:04}.txt"))).unwrap();
+            }
+        }
+    }
+
+    fn options(root: &str, batch_size: usize) -> ScanOptions {
+        ScanOptions {
+            filter: FileFilter::new(&[Category::Document], &[]),
+            excludes: Excludes::new(root, &[]).unwrap(),
+            hash: false,
+            batch_size,
+            resume: false,
+            progress: false,
+            record: true,
+        }
+    }
+
+    async fn count(db: &impl Database, root: &str) -> usize {
+        let q = SearchQuery {
+            path_prefix: Some(root.to_string()),
+            ..Default::default()
+        };
+        db.search(&q).try_collect::<Vec<_>>().await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        make_tree(&root, 3, 5);
+        let root = canonical(&root.display().to_string());
+
+        // the last batch is not a full one.
+        let report = scan(&mut db, &root, options(&root, 4)).await.unwrap();
+        assert_eq!(count(&db, &root).await, 3 + 3 * 5);
+        assert_eq!((report.new, report.updated), (3 + 3 * 5, 0));
+
+        // a narrower filter finds none of the files, it does not take them as gone.
+        let opts = ScanOptions {
+            filter: FileFilter::new(&[], &["srt".to_string()]),
+            ..options(&root, 4)
+        };
+        let report = scan(&mut db, &root, opts).await.unwrap();
+        assert_eq!(report.removed, 0);
+        assert_eq!(count(&db, &root).await, 3 + 3 * 5);
+
+        fs::remove_dir_all(Path::new(&root).join("dir0001")).unwrap();
+        fs::write(Path::new(&root).join("dir0000/file0000.txt"), "changed").unwrap();
+        let report = scan(&mut db, &root, options(&root, 4)).await.unwrap();
+        assert_eq!(count(&db, &root).await, 2 + 2 * 5);
+        assert_eq!(report.entries, 2 + 2 * 5);
+        assert_eq!((report.new, report.updated, report.removed), (0, 1, 1 + 5));
+        assert!(report.errors.is_empty());
+
+        let saved = db.scan_report(&report.id).await.unwrap().unwrap();
+        assert_eq!((saved.entries, saved.removed), (2 + 2 * 5, 1 + 5));
+
+        // one that is not recorded, as watch runs them, leaves the history alone.
+        let opts = ScanOptions {
+            record: false,
+            ..options(&root, 4)
+        };
+        let report = scan(&mut db, &root, opts).await.unwrap();
+        assert!(db.scan_report(&report.id).await.unwrap().is_none());
+        assert_eq!(db.scans(Some(&root), 10).await.unwrap().len(), 3);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_emptied() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        fs::create_dir_all(&root).unwrap();
+        fs::File::create(root.join("a.txt")).unwrap();
+        let root = canonical(&root.display().to_string());
+        scan(&mut db, &root, options(&root, 4)).await.unwrap();
+        assert_eq!(count(&db, &root).await, 1);
+
+        // nothing is left to find the volume by, the root still is on it.
+        fs::remove_file(Path::new(&root).join("a.txt")).unwrap();
+        let report = scan(&mut db, &root, options(&root, 4)).await.unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(count(&db, &root).await, 0);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_nfd() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        // decomposed, the way macOS names them.
+        let dir = root.join("Cafe\u{301}");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cafe\u{301} 1.txt"), "same").unwrap();
+        fs::write(dir.join("Cafe\u{301} 2.txt"), "same").unwrap();
+        let root = canonical(&root.display().to_string());
+
+        // the files are opened again to be hashed, by the path they have on disk.
+        let opts = ScanOptions {
+            hash: true,
+            ..options(&root, 4)
+        };
+        let report = scan(&mut db, &root, opts).await.unwrap();
+        assert!(report.errors.is_empty());
+        let q = SearchQuery {
+            name: "café".to_string(),
+            path_prefix: Some(root.clone()),
+            ..Default::default()
+        };
+        let found: Vec<File> = db.search(&q).try_collect().await.unwrap();
+        assert_eq!(found.len(), 3);
+        assert!(found.iter().filter(|f| !f.dir).all(|f| f.hash.is_some()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        make_tree(&root, 3, 5);
+        let root = canonical(&root.display().to_string());
+        scan(&mut db, &root, options(&root, 4)).await.unwrap();
+
+        // stopped before it found anything, and resumed once it did get through dir0001.
+        let stop = Stop::default();
+        stop.set();
+        let stopped = scan_until(&mut db, &root, options(&root, 4), &stop)
+            .await
+            .unwrap();
+        assert!(!stopped.complete);
+        let dir = Path::new(&root).join("dir0001");
+        let seen: Vec<_> = [dir.clone()]
+            .into_iter()
+            .chain(fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()))
+            .map(|p| {
+                let meta = fs::metadata(&p).unwrap();
+                let name = p.file_name().unwrap().to_string_lossy().to_string();
+                let f = File::new(p.display().to_string(), name, meta.is_dir(), None)
+                    .with_metadata(&meta);
+                match Volume::detect(&p) {
+                    Some(v) => f.with_volume(&v),
+                    None => f,
+                }
+            })
+            .collect();
+        db.save_bulk(&seen).await.unwrap();
+        let done = [dir.display().to_string()];
+        db.save_checkpoints(&stopped, &done).await.unwrap();
+
+        // what came after the checkpoint is not looked at again.
+        fs::File::create(dir.join("skipped.txt")).unwrap();
+        fs::File::create(Path::new(&root).join("dir0000/found.txt")).unwrap();
+        let opts = ScanOptions {
+            resume: true,
+            ..options(&root, 4)
+        };
+        let report = scan(&mut db, &root, opts).await.unwrap();
+        assert_eq!(report.id, stopped.id);
+        assert!(report.complete);
+        assert_eq!(report.removed, 0);
+        assert_eq!(count(&db, &root).await, 3 + 3 * 5 + 1);
+        assert!(db.checkpoints(&report.id).await.unwrap().is_empty());
+        let host = hostname();
+        assert!(db.unfinished_scan(&root, &host).await.unwrap().is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_errors() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        make_tree(&root, 2, 2);
+        let root = canonical(&root.display().to_string());
+        scan(&mut db, &root, options(&root, 4)).await.unwrap();
+
+        // a root that cannot be read is an error, not a tree that is all gone.
+        fs::remove_dir_all(&root).unwrap();
+        let report = scan(&mut db, &root, options(&root, 4)).await.unwrap();
+        assert_eq!(report.entries, 0);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, root);
+        assert_eq!(report.errors[0].kind, crate::report::ErrorKind::Io);
+        assert_eq!(count(&db, &root).await, 2 + 2 * 2);
+
+        let scans = db.scans(Some(&root), 10).await.unwrap();
+        assert_eq!(scans.len(), 2);
+        assert_eq!(
+            (scans[0].id.as_str(), scans[0].errored),
+            (report.id.as_str(), 1)
+        );
+        let saved = db.scan_report(&report.id[..8]).await.unwrap().unwrap();
+        assert_eq!(saved.errors, report.errors);
+    }
+
+    /// `cargo test --release bench_scan -- --ignored --nocapture`, `FINDV_BENCH_FILES` sets the
+    /// size of the tree.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_scan() {
+        log_init();
+        let entries: usize = std::env::var("FINDV_BENCH_FILES")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1_000_000);
+        let dir = std::env::temp_dir().join(uuid_v4());
+        let root = dir.join("tree");
+        make_tree(&root, entries / 1000, 999);
+        let root = canonical(&root.display().to_string());
+
+        let mut rate = 0.0;
+        for batch_size in [1, 1000] {
+            let mut db = Sqlite::new(dir.join(format!("bench{batch_size}.sqlite")))
+                .await
+                .unwrap();
+            let start = Instant::now();
+            scan(&mut db, &root, options(&root, batch_size))
+                .await
+                .unwrap();
+            let elapsed = start.elapsed();
+            rate = entries as f64 / elapsed.as_secs_f64();
+            println!("batch size {batch_size}: {entries} entries in {elapsed:?}, {rate:.0}/s");
+        }
+        fs::remove_dir_all(dir).unwrap();
+
+        // a million entries in well under two minutes, even on a single core.
+        assert!(rate > 10_000.0, "{rate:.0} entries/s");
+    }
 }
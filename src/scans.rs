@@ -0,0 +1,99 @@
+use crate::database::Database;
+use crate::report::ScanReport;
+use crate::scan::canonical;
+use chrono::Local;
+use clap::Subcommand;
+use eyre::{eyre, Result};
+
+#[derive(Debug, Subcommand)]
+pub enum ScansCommand {
+    /// past runs of `scan`, newest first, or one of them with the paths it failed on.
+    Scans {
+        /// id of the scan to show, or the start of one.
+        id: Option<String>,
+        /// only the scans of this root.
+        #[arg(long)]
+        root: Option<String>,
+        /// how many scans to list.
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: i64,
+    },
+}
+
+impl ScansCommand {
+    pub async fn run(self, db: &mut impl Database) -> Result<()> {
+        match self {
+            Self::Scans { id: Some(id), .. } => {
+                let report = db
+                    .scan_report(&id)
+                    .await?
+                    .ok_or_else(|| eyre!("no scan with id {id}."))?;
+                print!("{}", details(&report));
+            }
+            Self::Scans {
+                id: None,
+                root,
+                limit,
+            } => {
+                let root = root.map(|r| canonical(&r));
+                let reports = db.scans(root.as_deref(), limit).await?;
+                println!(
+                    "{:<8}  {:<19}  {:>8}  {:>8}  {:>6}  {:>7}  {:>7}  {:>6}  ROOT",
+                    "ID", "STARTED", "TOOK", "ENTRIES", "NEW", "UPDATED", "REMOVED", "FAILED"
+                );
+                for r in &reports {
+                    println!(
+                        "{:<8}  {}  {:>8}  {:>8}  {:>6}  {:>7}  {:>7}  {:>6}  {} ({})",
+                        &r.id[..8.min(r.id.len())],
+                        local_time(r),
+                        took(r),
+                        r.entries,
+                        r.new,
+                        r.updated,
+                        r.removed,
+                        r.errored,
+                        r.root,
+                        r.hostname
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// how long a scan ran, or that it stopped before it was done.
+fn took(r: &ScanReport) -> String {
+    if r.complete {
+        format!("{:.1}s", r.elapsed().as_secs_f64())
+    } else {
+        "stopped".to_string()
+    }
+}
+
+fn local_time(r: &ScanReport) -> String {
+    r.started
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// all there is to one scan, every error included.
+fn details(r: &ScanReport) -> String {
+    let mut s = format!(
+        "scan {} of {} on {}\nstarted {}, took {}\n{} entries: {} new, {} updated, {} removed, {} failed\n",
+        r.id,
+        r.root,
+        r.hostname,
+        local_time(r),
+        took(r),
+        r.entries,
+        r.new,
+        r.updated,
+        r.removed,
+        r.errored
+    );
+    s.push_str(&r.errors(usize::MAX));
+    s
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+/// how `SearchQuery::name` is matched.
+#[derive(Debug, Clone, Default)]
+pub enum SearchMode {
+    /// every word of the query is a substring of some word of the file name or of its pinyin,
+    /// in any order, `%` and `_` are matched literally.
+    #[default]
+    Substring,
+    /// fts5 query over names and paths: `show*`, `"the show"`, `a AND b`, `a OR b`, `a NOT b`,
+    /// best matches first.
+    FullText,
+    /// case sensitive shell glob, `*S01E0?*.mkv`.
+    Glob,
+    /// regular expression, `(?i)1080p|2160p`.
+    Regex(Regex),
+}
+
+/// what `find` asks the catalog for, bound as parameters rather than spliced into sql.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub name: String,
+    pub mode: SearchMode,
+    /// match against the full path instead of the file name, except for `FullText`.
+    pub full_path: bool,
+    pub kind: Option<Kind>,
+    /// lower case, without the dot, any of them.
+    pub extensions: Vec<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub modified_after: Option<DateTime<Utc>>,
+    pub modified_before: Option<DateTime<Utc>>,
+    pub scanned_after: Option<DateTime<Utc>>,
+    pub scanned_before: Option<DateTime<Utc>>,
+    /// host name with or without the `:user` part.
+    pub hostname: Option<String>,
+    /// directory the files are in, at any depth.
+    pub path_prefix: Option<String>,
+    /// media info of videos, see `MediaInfo`, files without it never match these.
+    pub min_width: Option<i64>,
+    pub min_height: Option<i64>,
+    pub min_duration_ms: Option<i64>,
+    pub max_duration_ms: Option<i64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// with an audio track in this language, see `media_info::language_codes`.
+    pub audio_lang: Option<String>,
+    /// with a subtitle track in this language.
+    pub subtitle_lang: Option<String>,
+    /// tags of songs, see `Tags`, matched case insensitively anywhere in the tag.
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// also matches the title of videos.
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i64>,
+    /// table order, or best match first for `FullText`, if not set.
+    pub sort: Option<Sort>,
+    pub reverse: bool,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Sort {
+    Name,
+    Path,
+    Size,
+    /// modification time.
+    Mtime,
+    /// when the file was first scanned.
+    Scanned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Kind {
+    File,
+    Dir,
+}
+
+/// canonical (nfc) form of a name, so the decomposed names macOS hands out compare equal to
+/// what is typed in a terminal. a path is saved as it is on disk and in this form both.
+pub fn normalize(s: &str) -> String {
+    s.nfc().collect()
+}
+
+/// form names are matched in: compatibility composed (nfkc), which also turns full width
+/// letters and digits into ascii, and lower case.
+pub fn search_key(s: &str) -> String {
+    s.nfkc().collect::<String>().to_lowercase()
+}
+
+/// split a file name into lower case words on dots, underscores, dashes, brackets and spaces,
+/// `The.Show.S02E05.1080p.WEB-DL.mkv` gives `the show s02e05 1080p web dl mkv`.
+pub fn tokenize(s: &str) -> Vec<String> {
+    search_key(s)
+        .split(|c: char| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '.' | '_' | '-' | '[' | ']' | '(' | ')' | '{' | '}' | '【' | '】' | '「' | '」'
+                )
+        })
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// escape the `like` metacharacters of `s` so it is matched literally with `escape '\'`.
+pub fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("The.Show.S02E05.1080p.WEB-DL.mkv"),
+            vec!["the", "show", "s02e05", "1080p", "web", "dl", "mkv"]
+        );
+        assert_eq!(
+            tokenize("[字幕组] go语言基础_(2023) {final}.mp4"),
+            vec!["字幕组", "go语言基础", "2023", "final", "mp4"]
+        );
+        assert!(tokenize(" ..-_ ").is_empty());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let nfd = "Cafe\u{301}.Ｓ０１.mkv";
+        assert_eq!(normalize(nfd), "Caf\u{e9}.Ｓ０１.mkv");
+        assert_eq!(tokenize(nfd), vec!["caf\u{e9}", "s01", "mkv"]);
+        assert_eq!(tokenize(nfd), tokenize("CAFÉ s01 MKV"));
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("Tom's"), "Tom's");
+        assert_eq!(escape_like("100%_done\\"), "100\\%\\_done\\\\");
+    }
+}
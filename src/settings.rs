@@ -1,3 +1,4 @@
+use crate::media::Category;
 use crate::util;
 use eyre::{eyre, Context, Result};
 use fs_err::create_dir_all;
@@ -13,6 +14,31 @@ pub struct Settings {
     pub db_name: String,
     #[serde(skip_deserializing, skip_serializing)]
     pub db_path: String,
+    #[serde(default)]
+    pub scan: ScanSettings,
+}
+
+/// which files `scan` records, besides directories.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    pub categories: Vec<Category>,
+    pub extensions: Vec<String>,
+    /// gitignore style patterns of paths not to walk, `/` anchors them at the root of the disk.
+    pub excludes: Vec<String>,
+    /// files saved per transaction.
+    pub batch_size: usize,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            categories: vec![Category::Video, Category::Audio],
+            extensions: vec![],
+            excludes: vec!["/Volumes/Macintosh*".to_string()],
+            batch_size: 1000,
+        }
+    }
 }
 
 impl Settings {
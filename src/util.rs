@@ -1,3 +1,4 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use std::path::PathBuf;
 use uuid::Uuid;
 
@@ -15,3 +16,138 @@ pub fn config_dir() -> PathBuf {
         .map_or_else(|_| home_dir().join(".config"), PathBuf::from);
     config_dir.join("find-videos")
 }
+
+/// `hostname:username` of the current machine, used to tag where a record was seen.
+pub fn hostname() -> String {
+    let host = whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string());
+    format!("{}:{}", host, whoami::username())
+}
+
+/// parse a byte size such as `2G`, `1.5GiB`, `500mb` or `1024`, units are powers of 1024.
+pub fn parse_size(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size `{s}`"))?;
+    let shift = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return Err(format!("invalid size unit `{unit}`")),
+    };
+
+    Ok((number * (1u64 << shift) as f64) as i64)
+}
+
+/// parse a length of time such as `90`, `90s`, `45m` or `1h30m` into milliseconds, a bare
+/// number is seconds.
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Ok((seconds * 1000.0) as i64);
+    }
+
+    let mut ms = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3_600_000.0,
+            'm' => 60_000.0,
+            's' => 1000.0,
+            _ => return Err(format!("invalid duration unit `{c}`, expect h, m or s")),
+        };
+        let n: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration `{s}`"))?;
+        ms += n * unit;
+        number.clear();
+    }
+    if !number.is_empty() || s.is_empty() {
+        return Err(format!("invalid duration `{s}`, expect 90s, 45m or 1h30m"));
+    }
+
+    Ok(ms as i64)
+}
+
+/// `size` in the largest unit of 1024 it reaches, `4.2 GiB`.
+pub fn format_size(size: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// parse a point in time given as rfc3339, `2022-03-01 12:00:00` or `2022-03-01` in local time.
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN)))
+        .map_err(|_| format!("invalid time `{s}`, expect 2022-03-01 or 2022-03-01 12:00:00"))?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("invalid local time `{s}`"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1.5kb"), Ok(1536));
+        assert_eq!(parse_size("500 MiB"), Ok(500 * 1024 * 1024));
+        assert!(parse_size("2X").is_err());
+        assert!(parse_size("G").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(90_000));
+        assert_eq!(parse_duration("45m"), Ok(45 * 60_000));
+        assert_eq!(parse_duration("1h30m"), Ok(90 * 60_000));
+        assert_eq!(parse_duration("1.5s"), Ok(1500));
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("1h30").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(42), "42 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(parse_size("4.2G").unwrap()), "4.2 GiB");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let t = parse_time("2022-03-01T12:00:00Z").unwrap();
+        assert_eq!(t, Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap());
+        assert!(parse_time("2022-03-01").is_ok());
+        assert!(parse_time("2022-03-01 08:30:00").is_ok());
+        assert!(parse_time("yesterday").is_err());
+    }
+}
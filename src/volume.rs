@@ -0,0 +1,452 @@
+use crate::util;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// a disk or share files were scanned on, identified by its filesystem uuid when there is one
+/// so it is recognized wherever it is mounted. the uuid is read on linux and macos, elsewhere a
+/// volume is known by its host and mount point.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Volume {
+    pub id: String,
+    pub uuid: Option<String>,
+    pub label: Option<String>,
+    pub fs_type: Option<String>,
+    pub mount_point: String,
+    pub hostname: String,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+}
+
+impl Volume {
+    fn new(
+        uuid: Option<String>,
+        label: Option<String>,
+        fs_type: Option<String>,
+        mount_point: String,
+    ) -> Self {
+        let hostname = util::hostname();
+        let id = uuid
+            .clone()
+            .unwrap_or_else(|| format!("{hostname}:{mount_point}"));
+        Self {
+            id,
+            uuid,
+            label,
+            fs_type,
+            mount_point,
+            hostname,
+            last_seen: Utc::now(),
+        }
+    }
+
+    /// the volume `path` is on.
+    pub fn detect(path: &Path) -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        if let Some(v) = linux::detect(path) {
+            return Some(v);
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(v) = macos::detect(path) {
+            return Some(v);
+        }
+
+        let mount_point = mount_point(path)?;
+        let label = mount_point
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        Some(Self::new(
+            None,
+            label,
+            None,
+            mount_point.display().to_string(),
+        ))
+    }
+
+    /// label, uuid or mount point, whichever tells a person the most.
+    pub fn name(&self) -> &str {
+        self.label
+            .as_deref()
+            .or(self.uuid.as_deref())
+            .unwrap_or(&self.mount_point)
+    }
+
+    /// `path` relative to the mount point, `None` when it is not below it.
+    pub fn relative(&self, path: &str) -> Option<String> {
+        let rest = path.strip_prefix(self.mount_point.trim_end_matches('/'))?;
+        if rest.is_empty() {
+            return Some(String::new());
+        }
+        rest.strip_prefix('/').map(str::to_string)
+    }
+
+    /// whether the volume is mounted on this machine right now, at any mount point.
+    pub fn is_mounted(&self) -> bool {
+        let here = Path::new(&self.mount_point);
+        if here.exists() && Self::detect(here).map(|v| v.id == self.id) == Some(true) {
+            return true;
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.uuid.is_some() {
+            return linux::mounts()
+                .iter()
+                .filter_map(|m| linux::detect(Path::new(&m.mount_point)))
+                .any(|v| v.id == self.id);
+        }
+        #[cfg(target_os = "macos")]
+        if self.uuid.is_some() {
+            return std::fs::read_dir("/Volumes")
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|e| macos::detect(&e.path()))
+                .any(|v| v.id == self.id);
+        }
+
+        false
+    }
+}
+
+/// highest ancestor of `path` on the same device, the mount point of its filesystem.
+#[cfg(unix)]
+fn mount_point(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = path.canonicalize().ok()?;
+    let dev = path.metadata().ok()?.dev();
+    let mut mount = path.clone();
+    for dir in path.ancestors().skip(1) {
+        match dir.metadata() {
+            Ok(meta) if meta.dev() == dev => mount = dir.to_path_buf(),
+            _ => break,
+        }
+    }
+    Some(mount)
+}
+
+#[cfg(not(unix))]
+fn mount_point(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    path.ancestors().last().map(Path::to_path_buf)
+}
+
+/// uuid and label out of the first blocks of a device, for the filesystems disks usually carry.
+pub fn probe(buf: &[u8]) -> Option<(String, Option<String>)> {
+    let text = |range: std::ops::Range<usize>| -> Option<String> {
+        let bytes = buf.get(range)?;
+        let s = String::from_utf8_lossy(bytes)
+            .trim_end_matches(['\0', ' '])
+            .to_string();
+        (!s.is_empty()).then_some(s)
+    };
+    let serial = |at: usize| -> Option<String> {
+        let b = buf.get(at..at + 4)?;
+        Some(format!("{:02X}{:02X}-{:02X}{:02X}", b[3], b[2], b[1], b[0]))
+    };
+
+    // ext2/3/4, superblock at 1024 with magic 0xef53.
+    if buf.get(1024 + 0x38..1024 + 0x3a) == Some(&[0x53, 0xef]) {
+        let u = buf.get(1024 + 0x68..1024 + 0x78)?;
+        let hex: Vec<String> = u.iter().map(|b| format!("{b:02x}")).collect();
+        let uuid = format!(
+            "{}-{}-{}-{}-{}",
+            hex[0..4].concat(),
+            hex[4..6].concat(),
+            hex[6..8].concat(),
+            hex[8..10].concat(),
+            hex[10..16].concat()
+        );
+        return Some((uuid, text(1024 + 0x78..1024 + 0x88)));
+    }
+
+    if buf.get(3..11) == Some(b"EXFAT   ") {
+        return Some((serial(0x64)?, None));
+    }
+
+    if buf.get(3..11) == Some(b"NTFS    ") {
+        let b = buf.get(0x48..0x50)?;
+        let uuid: String = b.iter().rev().map(|b| format!("{b:02X}")).collect();
+        return Some((uuid, None));
+    }
+
+    if buf.get(0x52..0x5a) == Some(b"FAT32   ") {
+        return Some((serial(0x43)?, text(0x47..0x52)));
+    }
+
+    if buf.get(0x36..0x3e).map(|t| t.starts_with(b"FAT1")) == Some(true) {
+        return Some((serial(0x27)?, text(0x2b..0x36)));
+    }
+
+    None
+}
+
+/// volume uuid, name and filesystem out of what `diskutil info -plist` prints.
+#[cfg(any(target_os = "macos", test))]
+fn parse_diskutil(plist: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let value = |key: &str| -> Option<String> {
+        let (_, rest) = plist.split_once(&format!("<key>{key}</key>"))?;
+        let rest = rest.trim_start().strip_prefix("<string>")?;
+        let (v, _) = rest.split_once("</string>")?;
+        let v = v
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&");
+        (!v.is_empty()).then_some(v)
+    };
+    (
+        value("VolumeUUID"),
+        value("VolumeName"),
+        value("FilesystemType"),
+    )
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{mount_point, parse_diskutil, Volume};
+    use std::path::Path;
+    use std::process::Command;
+
+    /// the volume with the uuid `diskutil` knows it by, which stays the same when the disk is
+    /// mounted at `/Volumes/Media 1` the next time. none for shares, they have no uuid.
+    pub fn detect(path: &Path) -> Option<Volume> {
+        let mount = mount_point(path)?;
+        let out = Command::new("diskutil")
+            .args(["info", "-plist"])
+            .arg(&mount)
+            .output()
+            .ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        let (uuid, label, fs_type) = parse_diskutil(&String::from_utf8_lossy(&out.stdout));
+        uuid.as_ref()?;
+        Some(Volume::new(
+            uuid,
+            label,
+            fs_type,
+            mount.display().to_string(),
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{probe, Volume};
+    use std::fs;
+    use std::io::Read;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct Mount {
+        pub major: u64,
+        pub minor: u64,
+        pub mount_point: String,
+        pub fs_type: String,
+        pub source: String,
+    }
+
+    /// `\040` and friends in mountinfo back into the characters they stand for.
+    fn unescape(s: &str) -> String {
+        let bytes = s.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .filter(|o| o.iter().all(|b| (b'0'..=b'7').contains(b)));
+            match octal {
+                Some(o) if bytes[i] == b'\\' => {
+                    out.push(o.iter().fold(0u8, |c, b| c.wrapping_mul(8) + (b - b'0')));
+                    i += 4;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).to_string()
+    }
+
+    pub fn parse_mountinfo(line: &str) -> Option<Mount> {
+        let (left, right) = line.split_once(" - ")?;
+        let left: Vec<&str> = left.split(' ').collect();
+        let mut right = right.split(' ');
+        let (major, minor) = left.get(2)?.split_once(':')?;
+        Some(Mount {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+            mount_point: unescape(left.get(4)?),
+            fs_type: right.next()?.to_string(),
+            source: unescape(right.next()?),
+        })
+    }
+
+    pub fn mounts() -> Vec<Mount> {
+        fs::read_to_string("/proc/self/mountinfo")
+            .map(|s| s.lines().filter_map(parse_mountinfo).collect())
+            .unwrap_or_default()
+    }
+
+    /// name of the `/dev/disk/by-*` link pointing at `source`, if udev made one.
+    fn by_link(kind: &str, source: &str) -> Option<String> {
+        let source = fs::canonicalize(source).ok()?;
+        fs::read_dir(format!("/dev/disk/{kind}"))
+            .ok()?
+            .flatten()
+            .find(|e| fs::canonicalize(e.path()).ok().as_ref() == Some(&source))
+            .map(|e| e.file_name().to_string_lossy().replace("\\x20", " "))
+    }
+
+    fn probe_device(source: &str) -> Option<(String, Option<String>)> {
+        let mut buf = vec![0; 2048];
+        fs::File::open(source).ok()?.read_exact(&mut buf).ok()?;
+        probe(&buf)
+    }
+
+    pub fn detect(path: &Path) -> Option<Volume> {
+        let meta = fs::metadata(path).ok()?;
+        let (dev_major, dev_minor) = (major(meta.dev()), minor(meta.dev()));
+        let path = path.canonicalize().ok()?;
+        let mount = mounts()
+            .into_iter()
+            .filter(|m| m.major == dev_major && m.minor == dev_minor)
+            .filter(|m| path.starts_with(&m.mount_point))
+            .max_by_key(|m| m.mount_point.len())?;
+
+        let (mut uuid, mut label) = (None, None);
+        if mount.source.starts_with("/dev/") {
+            uuid = by_link("by-uuid", &mount.source);
+            label = by_link("by-label", &mount.source);
+            if uuid.is_none() {
+                if let Some((u, l)) = probe_device(&mount.source) {
+                    uuid = Some(u);
+                    label = label.or(l);
+                }
+            }
+        }
+
+        Some(Volume::new(
+            uuid,
+            label,
+            Some(mount.fs_type),
+            mount.mount_point,
+        ))
+    }
+
+    fn major(dev: u64) -> u64 {
+        ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0x0000_0fff)
+    }
+
+    fn minor(dev: u64) -> u64 {
+        ((dev >> 12) & 0xffff_ff00) | (dev & 0x0000_00ff)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_parse_mountinfo() {
+            let line = "36 35 8:17 / /media/Media\\0401 rw,noatime shared:1 - exfat /dev/sdb1 rw";
+            assert_eq!(
+                parse_mountinfo(line),
+                Some(Mount {
+                    major: 8,
+                    minor: 17,
+                    mount_point: "/media/Media 1".to_string(),
+                    fs_type: "exfat".to_string(),
+                    source: "/dev/sdb1".to_string(),
+                })
+            );
+            assert!(!mounts().is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_probe() {
+        let mut ext4 = vec![0; 2048];
+        ext4[1024 + 0x38..1024 + 0x3a].copy_from_slice(&[0x53, 0xef]);
+        ext4[1024 + 0x68..1024 + 0x78].copy_from_slice(&[
+            0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab,
+            0xcd, 0xef,
+        ]);
+        ext4[1024 + 0x78..1024 + 0x7d].copy_from_slice(b"Media");
+        assert_eq!(
+            probe(&ext4),
+            Some((
+                "12345678-9abc-def0-0123-456789abcdef".to_string(),
+                Some("Media".to_string())
+            ))
+        );
+
+        let mut exfat = vec![0; 2048];
+        exfat[3..11].copy_from_slice(b"EXFAT   ");
+        exfat[0x64..0x68].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(probe(&exfat), Some(("1234-5678".to_string(), None)));
+
+        let mut fat32 = vec![0; 2048];
+        fat32[0x52..0x5a].copy_from_slice(b"FAT32   ");
+        fat32[0x43..0x47].copy_from_slice(&[0xef, 0xbe, 0xad, 0xde]);
+        fat32[0x47..0x52].copy_from_slice(b"USB DISK   ");
+        assert_eq!(
+            probe(&fat32),
+            Some(("DEAD-BEEF".to_string(), Some("USB DISK".to_string())))
+        );
+
+        assert_eq!(probe(&[0; 2048]), None);
+    }
+
+    #[test]
+    fn test_parse_diskutil() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>FilesystemType</key>
+	<string>exfat</string>
+	<key>MountPoint</key>
+	<string>/Volumes/Media 1</string>
+	<key>VolumeName</key>
+	<string>Films &amp; Shows</string>
+	<key>VolumeUUID</key>
+	<string>0E239BC6-F960-3107-89CF-1C97F78BB46B</string>
+</dict>
+</plist>"#;
+        assert_eq!(
+            parse_diskutil(plist),
+            (
+                Some("0E239BC6-F960-3107-89CF-1C97F78BB46B".to_string()),
+                Some("Films & Shows".to_string()),
+                Some("exfat".to_string())
+            )
+        );
+        assert_eq!(parse_diskutil("<dict></dict>"), (None, None, None));
+    }
+
+    #[test]
+    fn test_detect() {
+        let v = Volume::detect(Path::new("Cargo.toml")).unwrap();
+        assert!(Path::new("Cargo.toml")
+            .canonicalize()
+            .unwrap()
+            .starts_with(&v.mount_point));
+        assert!(v.is_mounted());
+
+        let mut v = v;
+        v.mount_point = "/Volumes/Media".to_string();
+        assert_eq!(
+            v.relative("/Volumes/Media/films/a.mkv"),
+            Some("films/a.mkv".to_string())
+        );
+        assert_eq!(v.relative("/Volumes/Media"), Some(String::new()));
+        assert_eq!(v.relative("/Volumes/Media 1/films/a.mkv"), None);
+        v.mount_point = "/".to_string();
+        assert_eq!(v.relative("/tmp/a.mkv"), Some("tmp/a.mkv".to_string()));
+    }
+}
@@ -0,0 +1,605 @@
+use crate::database::Database;
+use crate::exclude::{Excludes, IGNORE_FILE};
+use crate::file::File;
+use crate::media::FileFilter;
+use crate::scan::{
+    canonical, read_media_info, scan, ScanArgs, ScanOptions, Stop, DEFAULT_VOLUMES_PATH,
+};
+use crate::settings::Settings;
+use crate::util::parse_duration;
+use crate::volume::Volume;
+use chrono::Utc;
+use clap::Subcommand;
+use eyre::Result;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Subcommand)]
+pub enum WatchCommand {
+    /// scan the roots, then keep the catalog in step with them as files come, go and change.
+    Watch {
+        /// directories to watch, `/Volumes` if none.
+        roots: Vec<String>,
+        #[command(flatten)]
+        args: ScanArgs,
+        /// how long changes have to settle before they are saved, `0.5s`, `5s`. changes that
+        /// keep coming are saved every 10s all the same.
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        debounce: i64,
+    },
+}
+
+impl WatchCommand {
+    pub async fn run(self, db: &mut impl Database, settings: &Settings) -> Result<()> {
+        match self {
+            Self::Watch {
+                roots,
+                args,
+                debounce,
+            } => {
+                let roots = if roots.is_empty() {
+                    vec![DEFAULT_VOLUMES_PATH.to_string()]
+                } else {
+                    roots
+                };
+                let roots: Vec<PathBuf> = roots.iter().map(|r| canonical(r).into()).collect();
+                let patterns = args.patterns(settings);
+                let mut catalog = Catalog {
+                    excludes: roots
+                        .iter()
+                        .map(|r| Ok((r.clone(), Excludes::new(r, &patterns)?)))
+                        .collect::<Result<_>>()?,
+                    db,
+                    filter: args.filter(settings),
+                    patterns,
+                    batch_size: settings.scan.batch_size.max(1),
+                    volumes: HashMap::new(),
+                    roots,
+                    stopped: false,
+                };
+
+                let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+                let mut watcher = notify::recommended_watcher(move |res| {
+                    // the receiver is only gone when watch is done.
+                    let _ = tx.send(res);
+                })?;
+                // watched before the first scan, so nothing that changes during it is missed.
+                for root in &catalog.roots {
+                    watcher.watch(root, RecursiveMode::Recursive)?;
+                }
+                for root in catalog.roots.clone() {
+                    info!("scanning {}", root.display());
+                    catalog.rescan(&root).await?;
+                    if catalog.stopped {
+                        info!("stopped watching.");
+                        return Ok(());
+                    }
+                }
+                info!("watching {} roots", catalog.roots.len());
+
+                let debounce = Duration::from_millis(debounce.max(0) as u64);
+                let ctrl_c = tokio::signal::ctrl_c();
+                tokio::pin!(ctrl_c);
+                while !catalog.stopped {
+                    let mut batch = Batch::default();
+                    tokio::select! {
+                        res = rx.recv() => match res {
+                            Some(res) => batch.add(res, &catalog.roots),
+                            None => break,
+                        },
+                        _ = &mut ctrl_c => break,
+                    }
+                    let max_wait = MAX_LATENCY.max(debounce);
+                    if !settle(
+                        &mut rx,
+                        &mut batch,
+                        &catalog.roots,
+                        debounce,
+                        max_wait,
+                        &mut ctrl_c,
+                    )
+                    .await
+                    {
+                        break;
+                    }
+                    catalog.apply(batch).await?;
+                }
+                info!("stopped watching.");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// the longest a batch is held back while changes keep coming, a copy that takes an hour is
+/// saved as it goes.
+const MAX_LATENCY: Duration = Duration::from_secs(10);
+
+/// take more events into `batch` until nothing happened for `debounce`, or `max_wait` passed.
+/// false when `stop` came first.
+async fn settle<O>(
+    rx: &mut UnboundedReceiver<notify::Result<notify::Event>>,
+    batch: &mut Batch,
+    roots: &[PathBuf],
+    debounce: Duration,
+    max_wait: Duration,
+    stop: &mut (impl Future<Output = O> + Unpin),
+) -> bool {
+    let deadline = Instant::now() + max_wait;
+    while Instant::now() < deadline {
+        let quiet = (Instant::now() + debounce).min(deadline);
+        tokio::select! {
+            res = tokio::time::timeout_at(quiet, rx.recv()) => match res {
+                Ok(Some(res)) => batch.add(res, roots),
+                _ => break,
+            },
+            _ = &mut *stop => return false,
+        }
+    }
+    true
+}
+
+/// what changed under the roots since the last batch was saved.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Batch {
+    /// paths to look up on disk, in order so directories come before what is in them, and
+    /// whether they were created.
+    touched: BTreeMap<PathBuf, bool>,
+    /// renames that both ends were seen of, in order.
+    renames: Vec<(PathBuf, PathBuf)>,
+    /// directories to walk again, the backend lost track of what happened in them.
+    rescans: Vec<PathBuf>,
+}
+
+impl Batch {
+    fn add(&mut self, res: notify::Result<notify::Event>, roots: &[PathBuf]) {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("watch error:{}", e);
+                self.rescans.extend(e.paths);
+                return;
+            }
+        };
+        debug!("watch event:{:?}", event);
+
+        if event.need_rescan() {
+            // an overflowed queue does not say where events were lost.
+            if event.paths.is_empty() {
+                self.rescans.extend_from_slice(roots);
+            } else {
+                self.rescans.extend(event.paths);
+            }
+            return;
+        }
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let mut paths = event.paths.into_iter();
+                self.renames
+                    .push((paths.next().unwrap(), paths.next().unwrap()));
+            }
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for p in event.paths {
+                    self.touched.insert(p, true);
+                }
+            }
+            _ => {
+                for p in event.paths {
+                    self.touched.entry(p).or_insert(false);
+                }
+            }
+        }
+    }
+}
+
+/// the catalog of the watched roots, with what it takes to bring paths under them up to date.
+struct Catalog<'a, D> {
+    db: &'a mut D,
+    roots: Vec<PathBuf>,
+    filter: FileFilter,
+    patterns: Vec<String>,
+    batch_size: usize,
+    /// per root, as `.findvignore` files are only read below it.
+    excludes: HashMap<PathBuf, Excludes>,
+    /// by device, looked up once like `scan` does.
+    volumes: HashMap<i64, Option<Volume>>,
+    /// a rescan was stopped by ctrl-c, which `scan` takes for itself, so watch stops too.
+    stopped: bool,
+}
+
+impl<'a, D: Database> Catalog<'a, D> {
+    fn root_of(&self, path: &Path) -> Option<PathBuf> {
+        self.roots.iter().find(|r| path.starts_with(r)).cloned()
+    }
+
+    /// save what changed in `batch`: rescans first, then renames, then whatever else was touched
+    /// as it is on disk now.
+    async fn apply(&mut self, batch: Batch) -> Result<()> {
+        let since = Utc::now();
+        let Batch {
+            mut touched,
+            renames,
+            mut rescans,
+        } = batch;
+
+        // rules changed, walk the directory they apply to again.
+        for path in touched.keys() {
+            if path.file_name() == Some(IGNORE_FILE.as_ref()) {
+                let Some(root) = self.root_of(path) else {
+                    continue;
+                };
+                self.excludes
+                    .insert(root.clone(), Excludes::new(&root, &self.patterns)?);
+                rescans.extend(path.parent().map(Path::to_path_buf));
+            }
+        }
+
+        let mut walked: Vec<PathBuf> = vec![];
+        for dir in rescans {
+            if !walked.iter().any(|w| dir.starts_with(w)) {
+                self.rescan(&dir).await?;
+                if self.stopped {
+                    return Ok(());
+                }
+                walked.push(dir);
+            }
+        }
+
+        for (from, to) in renames {
+            if self.rename(&from, &to).await? {
+                touched.remove(&from);
+                touched.remove(&to);
+            } else {
+                // a plain delete and create then.
+                touched.entry(from).or_insert(false);
+                touched.insert(to, true);
+            }
+        }
+
+        for (path, created) in touched {
+            if walked.iter().any(|w| path.starts_with(w)) {
+                continue;
+            }
+            if self.update(&path, created).await? {
+                walked.push(path);
+            }
+            if self.stopped {
+                return Ok(());
+            }
+        }
+
+        for root in &self.roots {
+            let root = root.display().to_string();
+            read_media_info(self.db, &root, since, &Stop::default()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// walk `dir` again like `scan`, or forget it when it is gone.
+    async fn rescan(&mut self, dir: &Path) -> Result<()> {
+        let Some(root) = self.root_of(dir) else {
+            return Ok(());
+        };
+        if !dir.is_dir() {
+            self.remove(dir).await?;
+            return Ok(());
+        }
+
+        debug!("rescan dir:{}", dir.display());
+        let opts = ScanOptions {
+            filter: self.filter.clone(),
+            excludes: self.excludes[&root].clone(),
+            hash: false,
+            batch_size: self.batch_size,
+            resume: false,
+            progress: false,
+            // a rescan of one directory is no scan of the root to list or resume.
+            record: false,
+        };
+        // what failed was logged as it happened.
+        let report = scan(self.db, &dir.display().to_string(), opts).await?;
+        self.stopped = !report.complete;
+        Ok(())
+    }
+
+    /// save `path` as it is on disk or remove it when it is gone, true when it was a new
+    /// directory and walked.
+    async fn update(&mut self, path: &Path, created: bool) -> Result<bool> {
+        let meta = match tokio::fs::metadata(path).await {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.remove(path).await?;
+                return Ok(false);
+            }
+            Err(e) => {
+                warn!("could not read {}:{}", path.display(), e);
+                return Ok(false);
+            }
+        };
+        let Some(f) = self.file(path, &meta).await? else {
+            return Ok(false);
+        };
+
+        if !created {
+            debug!("modified file:{}", f.full_path);
+            self.db.save_modified(&f).await?;
+            return Ok(false);
+        }
+        debug!("created file:{}", f.full_path);
+        self.db.save(&f).await?;
+        // what was in a directory moved or copied in has no events of its own.
+        if f.dir {
+            self.rescan(path).await?;
+        }
+        Ok(f.dir)
+    }
+
+    async fn remove(&mut self, path: &Path) -> Result<()> {
+        let removed = self.db.remove(&path.display().to_string()).await?;
+        for p in &removed {
+            debug!("removed file:{p}");
+        }
+        Ok(())
+    }
+
+    /// move what was known at `from` to `to`, false when `from` was not known or `to` is not
+    /// to be kept.
+    async fn rename(&mut self, from: &Path, to: &Path) -> Result<bool> {
+        let Ok(meta) = tokio::fs::metadata(to).await else {
+            return Ok(false);
+        };
+        let Some(f) = self.file(to, &meta).await? else {
+            return Ok(false);
+        };
+
+        let from = from.display().to_string();
+        let renamed = self.db.rename(&from, &f).await?;
+        if renamed {
+            debug!("renamed file:{from} to {}", f.full_path);
+        }
+        Ok(renamed)
+    }
+
+    /// the file to save for `path`, `None` when `scan` would have left it out.
+    async fn file(&mut self, path: &Path, meta: &Metadata) -> Result<Option<File>> {
+        let Some(root) = self.root_of(path) else {
+            return Ok(None);
+        };
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if !meta.is_dir() && !self.filter.matches(&name) {
+            return Ok(None);
+        }
+        // hidden or excluded, itself or a directory above it.
+        let excludes = self.excludes.get_mut(&root).unwrap();
+        for p in path.ancestors().take_while(|p| *p != root) {
+            let hidden = p
+                .file_name()
+                .map(|n| n.to_string_lossy().starts_with('.'))
+                .unwrap_or(false);
+            if hidden || excludes.is_excluded(p, p != path || meta.is_dir()) {
+                return Ok(None);
+            }
+        }
+
+        let mut f =
+            File::new(path.display().to_string(), name, meta.is_dir(), None).with_metadata(meta);
+        if !self.volumes.contains_key(&f.device) {
+            let volume = Volume::detect(path);
+            if let Some(v) = &volume {
+                self.db.save_volume(v).await?;
+            }
+            self.volumes.insert(f.device, volume);
+        }
+        if let Some(v) = &self.volumes[&f.device] {
+            f = f.with_volume(v);
+        }
+        Ok(Some(f))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Sqlite;
+    use crate::log::log_init;
+    use crate::media::Category;
+    use crate::search::SearchQuery;
+    use crate::util::uuid_v4;
+    use futures::TryStreamExt;
+    use notify::event::{CreateKind, Flag, RemoveKind};
+    use std::fs;
+
+    /// a catalog of `root` that keeps videos, as `watch` makes one.
+    fn catalog<'a>(db: &'a mut Sqlite, root: &Path) -> Catalog<'a, Sqlite> {
+        Catalog {
+            db,
+            roots: vec![root.to_path_buf()],
+            filter: FileFilter::new(&[Category::Video], &[]),
+            patterns: vec![],
+            batch_size: 100,
+            excludes: [(root.to_path_buf(), Excludes::new(root, &[]).unwrap())].into(),
+            volumes: HashMap::new(),
+            stopped: false,
+        }
+    }
+
+    /// ids, names and sizes of the files under `root`, by name.
+    async fn files(db: &Sqlite, root: &Path) -> Vec<(String, String, i64)> {
+        let q = SearchQuery {
+            path_prefix: Some(root.display().to_string()),
+            ..Default::default()
+        };
+        let mut found: Vec<_> = db
+            .search(&q)
+            .map_ok(|f| (f.id, f.file_name, f.size))
+            .try_collect()
+            .await
+            .unwrap();
+        found.sort_by(|a, b| a.1.cmp(&b.1));
+        found
+    }
+
+    fn names(files: &[(String, String, i64)]) -> Vec<&str> {
+        files.iter().map(|f| f.1.as_str()).collect()
+    }
+
+    #[test]
+    fn test_batch() {
+        log_init();
+        let roots = vec![PathBuf::from("/media")];
+        let events = [
+            notify::Event::new(EventKind::Create(CreateKind::File)).add_path("/media/a.mkv".into()),
+            notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path("/media/a.mkv".into()),
+            notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path("/media/b.mkv".into()),
+            notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path("/media/c.mkv".into())
+                .add_path("/media/d.mkv".into()),
+            notify::Event::new(EventKind::Remove(RemoveKind::File)).add_path("/media/e.mkv".into()),
+            notify::Event::new(EventKind::Other).set_flag(Flag::Rescan),
+        ];
+        let mut batch = Batch::default();
+        for e in events {
+            batch.add(Ok(e), &roots);
+        }
+
+        let touched = [
+            ("/media/a.mkv", true),
+            ("/media/b.mkv", false),
+            ("/media/e.mkv", false),
+        ];
+        assert_eq!(
+            batch,
+            Batch {
+                touched: touched.map(|(p, c)| (PathBuf::from(p), c)).into(),
+                renames: vec![("/media/c.mkv".into(), "/media/d.mkv".into())],
+                rescans: roots,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        log_init();
+        let mut db = Sqlite::new("./sofaraway.sqlite").await.unwrap();
+        let root = std::env::temp_dir().join(uuid_v4());
+        fs::create_dir_all(&root).unwrap();
+        let root = PathBuf::from(canonical(&root.display().to_string()));
+        fs::write(root.join("a.mkv"), b"a").unwrap();
+        let mut catalog = catalog(&mut db, &root);
+        catalog.rescan(&root).await.unwrap();
+        assert_eq!(names(&files(catalog.db, &root).await), vec!["a.mkv"]);
+
+        // created, and a text file that is no video.
+        fs::write(root.join("b.mkv"), b"b").unwrap();
+        fs::write(root.join("notes.txt"), b"notes").unwrap();
+        let mut batch = Batch::default();
+        batch.touched.insert(root.join("b.mkv"), true);
+        batch.touched.insert(root.join("notes.txt"), true);
+        catalog.apply(batch).await.unwrap();
+        let found = files(catalog.db, &root).await;
+        assert_eq!(names(&found), vec!["a.mkv", "b.mkv"]);
+        let b_id = found[1].0.clone();
+
+        // modified.
+        fs::write(root.join("a.mkv"), b"longer").unwrap();
+        let mut batch = Batch::default();
+        batch.touched.insert(root.join("a.mkv"), false);
+        catalog.apply(batch).await.unwrap();
+        assert_eq!(files(catalog.db, &root).await[0].2, 6);
+
+        // renamed, the row keeps its id.
+        fs::rename(root.join("b.mkv"), root.join("c.mkv")).unwrap();
+        let batch = Batch {
+            renames: vec![(root.join("b.mkv"), root.join("c.mkv"))],
+            ..Default::default()
+        };
+        catalog.apply(batch).await.unwrap();
+        let found = files(catalog.db, &root).await;
+        assert_eq!(names(&found), vec!["a.mkv", "c.mkv"]);
+        assert_eq!(found[1].0, b_id);
+
+        // deleted.
+        fs::remove_file(root.join("a.mkv")).unwrap();
+        let mut batch = Batch::default();
+        batch.touched.insert(root.join("a.mkv"), false);
+        catalog.apply(batch).await.unwrap();
+        assert_eq!(names(&files(catalog.db, &root).await), vec!["c.mkv"]);
+
+        // an overflowed queue lost what happened, the root is walked again.
+        fs::remove_file(root.join("c.mkv")).unwrap();
+        fs::write(root.join("d.mkv"), b"d").unwrap();
+        let mut batch = Batch::default();
+        let overflow = notify::Event::new(EventKind::Other).set_flag(Flag::Rescan);
+        batch.add(Ok(overflow), &catalog.roots);
+        catalog.apply(batch).await.unwrap();
+        assert_eq!(names(&files(catalog.db, &root).await), vec!["d.mkv"]);
+        assert!(!catalog.stopped);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_settle() {
+        log_init();
+        let roots = vec![PathBuf::from("/media")];
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        // a change every 20ms never goes quiet for 100ms.
+        let busy = tokio::spawn(async move {
+            for i in 0.. {
+                let e = notify::Event::new(EventKind::Create(CreateKind::File))
+                    .add_path(format!("/media/{i}.mkv").into());
+                if tx.send(Ok(e)).is_err() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        let started = Instant::now();
+        let mut batch = Batch::default();
+        let mut never = std::future::pending::<()>();
+        let settled = settle(
+            &mut rx,
+            &mut batch,
+            &roots,
+            Duration::from_millis(100),
+            Duration::from_millis(300),
+            &mut never,
+        )
+        .await;
+        assert!(settled);
+        assert!(started.elapsed() < Duration::from_millis(600));
+        assert!(!batch.touched.is_empty());
+
+        // stopped while changes still come in.
+        let mut stop = std::future::ready(());
+        let settled = settle(
+            &mut rx,
+            &mut Batch::default(),
+            &roots,
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            &mut stop,
+        )
+        .await;
+        assert!(!settled);
+        drop(rx);
+        busy.await.unwrap();
+    }
+}